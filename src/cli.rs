@@ -0,0 +1,311 @@
+// the command line: a subcommand followed by options. the commands that set up
+// a position and search it share the options below, the tool commands (trace,
+// tune, params, nnue, book, syzygy) read their own arguments
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use crate::book::OpeningBook;
+use crate::notation::{legal_moves, parse_fen, san, START_FEN};
+use crate::params::Params;
+use crate::random::Random;
+use crate::search::{SearchLimits, SearchParams, SearchResult, SearchStats, MAX_PLY};
+use crate::skill;
+use crate::tt::{zobrist, TranspositionTable};
+use crate::{find_best_move, game_over, Board};
+
+pub const USAGE:&str = "usage: chessbot [--params <file>] [--nnue <file>] [command] [options]
+
+commands:
+  play                  play a game against the engine in the terminal (the default)
+  uci                   speak the uci protocol on stdin and stdout
+  xboard                speak the xboard protocol on stdin and stdout
+  perft <depth>         count the leaf positions of the move tree, per root move
+  bench                 search a fixed set of positions and report nodes and speed
+  analyze               search one position and print every iteration
+  selfplay              let the engine play both sides and print the game
+  trace, tune, params, nnue, book, syzygy
+                        tools, run one without arguments to see its usage
+  help                  show this text
+
+options:
+  --fen <fen>           start from this position instead of the initial one
+  --depth <plies>       search depth, 1 to 128
+  --movetime <ms>       search time per move, searching as deep as it gets
+  --color <color>       the side the human plays: white, black or random
+  --book <file>         polyglot opening book to play from
+  --hash <mb>           size of the transposition table in megabytes
+
+a fen has spaces, so quote it: --fen \"8/8/8/4k3/8/8/3QK3/8 w - - 0 1\"";
+
+// the options each command takes, anything else is reported
+const POSITION_OPTIONS:&[&str] = &["--fen", "--depth", "--movetime", "--book", "--hash"];
+const PROTOCOL_OPTIONS:&[&str] = &["--depth", "--book", "--hash"];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Color{
+    White,
+    Black,
+    Random,
+}
+
+pub struct Options{
+    pub fen:Option<String>,
+    pub depth:Option<i32>,
+    pub movetime:Option<Duration>,
+    pub color:Color,
+    pub book:Option<String>,
+    pub hash:Option<usize>,
+    pub arguments:Vec<String>,      // what is left over, the perft depth
+}
+
+fn number<T:std::str::FromStr+PartialOrd+std::fmt::Display>(option:&str, value:&str, min:T, max:T)->Result<T, String>{
+    let number = value.parse::<T>().map_err(|_| format!("{} needs a number, not '{}'", option, value))?;
+    if number < min || number > max{
+        return Err(format!("{} must be between {} and {}, not {}", option, min, max, value));
+    }
+    Ok(number)
+}
+
+impl Options{
+    pub fn parse(command:&str, args:&[String], allowed:&[&str])->Result<Options, String>{
+        let mut options = Options{fen:None, depth:None, movetime:None, color:Color::White, book:None, hash:None, arguments:Vec::new()};
+        let mut args = args.iter();
+        while let Some(arg) = args.next(){
+            if !arg.starts_with("--"){
+                options.arguments.push(arg.clone());
+                continue;
+            }
+            if !allowed.contains(&arg.as_str()){
+                return Err(format!("{} does not take the option {}, see chessbot help", command, arg));
+            }
+            let Some(value) = args.next() else{
+                return Err(format!("{} needs a value, see chessbot help", arg));
+            };
+            match arg.as_str(){
+                "--fen"=>{
+                    parse_fen(value)?;
+                    options.fen = Some(value.clone());
+                },
+                "--depth"=>options.depth = Some(number(arg, value, 1, MAX_PLY as i32)?),
+                "--movetime"=>options.movetime = Some(Duration::from_millis(number(arg, value, 1, u64::MAX)?)),
+                "--color"=>options.color = match value.as_str(){
+                    "white"=>Color::White,
+                    "black"=>Color::Black,
+                    "random"=>Color::Random,
+                    _=>return Err(format!("--color is white, black or random, not '{}'", value)),
+                },
+                "--book"=>options.book = Some(value.clone()),
+                "--hash"=>options.hash = Some(number(arg, value, 1, 65536)?),
+                _=>unreachable!(),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn apply(&self, params:&mut Params){
+        // the options override the parameter file
+        if let Some(depth) = self.depth{
+            params.search.depth = depth;
+        }
+        if let Some(hash) = self.hash{
+            params.search.hash_mb = hash;
+        }
+        if let Some(book) = &self.book{
+            params.book.file = book.clone();
+        }
+    }
+
+    pub fn position(&self)->(Board, bool){
+        // the fen was checked when the options were parsed
+        parse_fen(self.fen.as_deref().unwrap_or(START_FEN)).unwrap()
+    }
+
+    pub fn start_ply(&self, white:bool)->usize{
+        // plies since the initial position, counted from the full move number of a fen
+        let full_moves = self.fen.as_deref().and_then(|fen| fen.split_whitespace().nth(5)).and_then(|moves| moves.parse::<usize>().ok()).unwrap_or(1);
+        2*full_moves.saturating_sub(1) + !white as usize
+    }
+
+    pub fn limits(&self, params:&SearchParams)->SearchLimits{
+        // with only a movetime the search goes as deep as the time allows
+        let depth = match (self.depth, self.movetime){
+            (None, Some(_))=>MAX_PLY as i32,
+            _=>params.depth,
+        };
+        SearchLimits{depth, movetime:self.movetime}
+    }
+}
+
+pub fn parse(command:&str, args:&[String], params:&mut Params)->Result<Options, String>{
+    // the options of a command, already applied to the parameters
+    let allowed:Vec<&str> = match command{
+        "play"=>[POSITION_OPTIONS, &["--color"]].concat(),
+        "uci" | "xboard"=>PROTOCOL_OPTIONS.to_vec(),
+        "perft"=>vec!["--fen"],
+        "bench"=>vec!["--depth", "--hash"],
+        _=>POSITION_OPTIONS.to_vec(),
+    };
+    let options = Options::parse(command, args, &allowed)?;
+    let positional = if command == "perft"{1}else{0};
+    if options.arguments.len() != positional{
+        return Err(match command{
+            "perft"=>"usage: chessbot perft <depth> [--fen <fen>]".to_string(),
+            _=>format!("{} takes no argument '{}', see chessbot help", command, options.arguments[positional]),
+        });
+    }
+    options.apply(params);
+    Ok(options)
+}
+
+pub fn perft(board:&Board, white:bool, depth:u32)->u64{
+    // the number of move sequences of the given length, the usual test of move generation.
+    // castling and en passant are never generated, so compare with published counts that lack them
+    let moves = legal_moves(board, white);
+    if depth <= 1{
+        return if depth == 0{1}else{moves.len() as u64};
+    }
+    moves.iter().map(|child| perft(child, !white, depth-1)).sum()
+}
+
+pub fn run_perft(options:&Options)->Result<(), String>{
+    let depth = number("perft", &options.arguments[0], 1, 10)?;
+    let (board, white) = options.position();
+    let start = Instant::now();
+    let mut total = 0;
+    for child in legal_moves(&board, white){
+        let count = perft(&child, !white, depth-1);
+        println!("{:8} {}", san(&board, &child, white), count);
+        total += count;
+    }
+    let time = start.elapsed();
+    println!("\nnodes {} time {}ms nps {}", total, time.as_millis(), (total as f64 / time.as_secs_f64().max(1e-3)) as u64);
+    Ok(())
+}
+
+// middlegames, endgames and a few tactics, each searched from an empty table
+const BENCH_FENS:&[&str] = &[
+    START_FEN,
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w - - 2 3",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP2BPPP/R2QKB1R w - - 0 8",
+    "2r3k1/pp3ppp/2n1b3/3p4/3P4/2PB1N2/P4PPP/R5K1 b - - 0 20",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "8/8/4k3/8/2p5/8/B2K4/8 w - - 0 1",
+    "r2q1rk1/ppp2ppp/2np1n2/2b1p1B1/2B1P1b1/2NP1N2/PPP2PPP/R2Q1RK1 w - - 0 8",
+    "3r1rk1/p4ppp/1p2p3/2q5/2P5/P3Q3/1P3PPP/3R1RK1 b - - 0 22",
+];
+const BENCH_DEPTH:i32 = 6;
+
+pub fn run_bench(options:&Options, params:&Params)->Result<(), String>{
+    // the total node count is a signature of the search, it only changes when
+    // the search or the evaluation does. the speed is the nodes per second
+    let limits = SearchLimits{depth:options.depth.unwrap_or(BENCH_DEPTH), movetime:None};
+    let search = SearchParams{threads:1, ..params.search};
+    let mut total = SearchStats::default();
+    for (index, fen) in BENCH_FENS.iter().enumerate(){
+        let (board, white) = parse_fen(fen)?;
+        let tt = TranspositionTable::new(search.hash_mb);
+        let result = find_best_move(board, white, &limits, &search, &tt, &AtomicBool::new(false), None);
+        println!("position {:2} {}", index+1, result.info_line(&board, white));
+        total.merge(&result.stats);
+        total.time += result.stats.time;
+    }
+    println!("\nnodes {} time {}ms nps {}", total.nodes, total.time.as_millis(), total.nps());
+    Ok(())
+}
+
+pub fn run_analyze(options:&Options, params:&Params)->Result<(), String>{
+    // every finished iteration is printed, and the lines at the end with multi-pv on
+    let (board, white) = options.position();
+    if legal_moves(&board, white).is_empty(){
+        game_over(&board, white);
+        return Ok(());
+    }
+    let limits = options.limits(&params.search);
+    let tt = TranspositionTable::new(params.search.hash_mb);
+    let mut report = |result:&SearchResult| println!("{}", result.info_line(&board, white));
+    let result = find_best_move(board, white, &limits, &params.search, &tt, &AtomicBool::new(false), Some(&mut report));
+    if params.search.multi_pv > 1{
+        println!("{}", result.lines_text(&board, white));
+    }
+    println!("best move {}", san(&board, &result.board, white));
+    Ok(())
+}
+
+pub fn run_selfplay(options:&Options, params:&Params)->Result<(), String>{
+    // the engine plays both sides from the book and its search until mate,
+    // stalemate, the fifty move rule or a threefold repetition, then prints the game
+    let (mut board, mut white) = options.position();
+    let limits = options.limits(&params.search);
+    let tt = TranspositionTable::new(params.search.hash_mb);
+    let mut book = OpeningBook::new(&params.book)?;
+    let mut random = Random::new(params.search.seed);
+    let mut seen:HashMap<u64, usize> = HashMap::new();
+    let mut previous:Option<Board> = None;
+    let mut moves:Vec<String> = Vec::new();
+    let mut ply = options.start_ply(white);
+    let result = loop{
+        if game_over(&board, white){
+            break if board.in_check(white){if white{"0-1"}else{"1-0"}}else{"1/2-1/2"};
+        }
+        if board.fifty_rule >= 100{
+            println!("draw by the fifty move rule");
+            break "1/2-1/2";
+        }
+        let repetitions = seen.entry(zobrist(&board, white)).or_insert(0);
+        *repetitions += 1;
+        if *repetitions >= 3{
+            println!("draw by threefold repetition");
+            break "1/2-1/2";
+        }
+        let next = match book.choose(&board, white, ply, previous.as_ref()){
+            Some(book_move)=>{
+                println!("{:8} book", san(&board, &book_move, white));
+                book_move
+            },
+            None=>{
+                let mut result = find_best_move(board, white, &limits, &params.search, &tt, &AtomicBool::new(false), None);
+                skill::choose(&params.search, &mut result, &mut random);
+                println!("{:8} {}", san(&board, &result.board, white), result.info_line(&board, white));
+                result.board
+            },
+        };
+        let number = if white{format!("{}. ", ply/2+1)}else if moves.is_empty(){format!("{}... ", ply/2+1)}else{String::new()};
+        moves.push(format!("{}{}", number, san(&board, &next, white)));
+        previous = Some(board);
+        board = next;
+        white = !white;
+        ply += 1;
+    };
+    println!();
+    if let Some(fen) = &options.fen{
+        println!("[FEN \"{}\"]", fen);
+    }
+    println!("[Result \"{}\"]\n", result);
+    println!("{} {}", moves.join(" "), result);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn perft_matches_the_published_start_position_counts(){
+        // no castling or en passant is possible this early
+        let (board, white) = parse_fen(START_FEN).unwrap();
+        let counts:Vec<u64> = (1..=4).map(|depth| perft(&board, white, depth)).collect();
+        assert_eq!(counts, [20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn perft_matches_the_published_counts_of_a_middlegame(){
+        // position 6 of the chess programming wiki, without castling rights or en passant
+        let (board, white) = parse_fen("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10").unwrap();
+        let counts:Vec<u64> = (1..=3).map(|depth| perft(&board, white, depth)).collect();
+        assert_eq!(counts, [46, 2079, 89890]);
+    }
+}
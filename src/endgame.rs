@@ -0,0 +1,423 @@
+// specialised evaluation of endgames the general terms get wrong, chosen by
+// the material on the board. squares in here are numbered a1=0, b1=1 .. h8=63
+// so files and ranks can be read off directly
+
+use std::sync::OnceLock;
+
+use crate::score::Score;
+use crate::Board;
+
+// clearly won but not a mate score, so the search still prefers real mates
+pub const KNOWN_WIN:i32 = 10000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Endgame{
+    InsufficientMaterial,
+    Kpk,
+    Kbnk,
+    Kxk,            // bare king against enough material to mate
+    WrongBishop,    // rook pawns with a bishop that does not control the promotion corner
+}
+
+impl Endgame{
+    pub fn name(self)->&'static str{
+        match self{
+            Endgame::InsufficientMaterial=>"insufficient material",
+            Endgame::Kpk=>"KPK",
+            Endgame::Kbnk=>"KBNK",
+            Endgame::Kxk=>"KXK",
+            Endgame::WrongBishop=>"wrong coloured bishop",
+        }
+    }
+}
+
+fn square(bitmap_square:u32)->usize{
+    // bitmap squares count from h1
+    (bitmap_square/8*8 + 7 - bitmap_square%8) as usize
+}
+
+fn file(square:usize)->i32{
+    (square%8) as i32
+}
+
+fn rank(square:usize)->i32{
+    (square/8) as i32
+}
+
+fn distance(a:usize, b:usize)->i32{
+    (file(a)-file(b)).abs().max((rank(a)-rank(b)).abs())
+}
+
+fn dark(square:usize)->bool{
+    // a1 is a dark square
+    (file(square)+rank(square)) % 2 == 0
+}
+
+fn edge_distance(square:usize)->i32{
+    file(square).min(7-file(square)).min(rank(square)).min(7-rank(square))
+}
+
+#[derive(Clone, Copy)]
+struct Side{
+    pawns:u64,
+    knights:u64,
+    bishops:u64,
+    rooks:u64,
+    queens:u64,
+    king:usize,
+}
+
+impl Side{
+    fn of(board:&Board, color_map:u64)->Side{
+        Side{
+            pawns:board.pawns & color_map,
+            knights:board.knights & color_map,
+            bishops:board.bishops & color_map,
+            rooks:board.rooks & color_map,
+            queens:board.queens & color_map,
+            king:square((board.kings & color_map).trailing_zeros()),
+        }
+    }
+
+    fn pieces(&self)->u64{
+        self.knights|self.bishops|self.rooks|self.queens
+    }
+
+    fn bare(&self)->bool{
+        self.pawns|self.pieces() == 0
+    }
+
+    fn bishop_pair(&self)->bool{
+        // bishops on both colours, same coloured bishops cannot mate
+        let mut colours = [false, false];
+        let mut bishops = self.bishops;
+        while bishops != 0{
+            colours[dark(square(bishops.trailing_zeros())) as usize] = true;
+            bishops &= bishops-1;
+        }
+        colours[0] && colours[1]
+    }
+
+    fn minors(&self)->u32{
+        (self.knights|self.bishops).count_ones()
+    }
+}
+
+pub fn recognise(board:&Board)->Option<(Endgame, bool)>{
+    // the endgame and whether white is the stronger side
+    if (board.kings & board.whites).count_ones() != 1 || (board.kings & board.blacks).count_ones() != 1{
+        return None;
+    }
+    let white = Side::of(board, board.whites);
+    let black = Side::of(board, board.blacks);
+
+    let no_major = |side:&Side| side.pawns|side.rooks|side.queens == 0;
+    if no_major(&white) && no_major(&black){
+        let knights_only = |side:&Side| side.bishops == 0 && side.knights.count_ones() == 2;
+        if (white.minors() <= 1 && black.minors() <= 1)
+            || (knights_only(&white) && black.bare()) || (knights_only(&black) && white.bare()){
+            return Some((Endgame::InsufficientMaterial, true));
+        }
+    }
+
+    for (strong, weak, strong_white) in [(&white, &black, true), (&black, &white, false)]{
+        if !weak.bare(){
+            continue;
+        }
+        if strong.pieces() == 0 && strong.pawns.count_ones() == 1{
+            return Some((Endgame::Kpk, strong_white));
+        }
+        if strong.pawns == 0 && strong.rooks|strong.queens == 0
+            && strong.knights.count_ones() == 1 && strong.bishops.count_ones() == 1{
+            return Some((Endgame::Kbnk, strong_white));
+        }
+        if strong.pawns != 0 && strong.pieces() == strong.bishops && wrong_bishop(strong, weak, strong_white){
+            return Some((Endgame::WrongBishop, strong_white));
+        }
+        if strong.pawns == 0 && (strong.rooks|strong.queens != 0 || strong.bishop_pair()){
+            return Some((Endgame::Kxk, strong_white));
+        }
+    }
+    None
+}
+
+fn wrong_bishop(strong:&Side, weak:&Side, strong_white:bool)->bool{
+    // all pawns on one rook file, every bishop on the other colour than the
+    // promotion square and the defending king already next to that square
+    const FILE_A:u64 = 0x8080_8080_8080_8080;
+    const FILE_H:u64 = 0x0101_0101_0101_0101;
+    let promotion_file = if strong.pawns & !FILE_A == 0{
+        0
+    }else if strong.pawns & !FILE_H == 0{
+        7
+    }else{
+        return false;
+    };
+    let promotion = if strong_white{56 + promotion_file}else{promotion_file};
+    let mut bishops = strong.bishops;
+    while bishops != 0{
+        if dark(square(bishops.trailing_zeros())) == dark(promotion){
+            return false;
+        }
+        bishops &= bishops-1;
+    }
+    distance(weak.king, promotion) <= 1
+}
+
+pub fn evaluate(board:&Board, white:bool)->Option<Score>{
+    // score for the side to move, None when no specialised evaluator applies
+    let (endgame, strong_white) = recognise(board)?;
+    let strong = Side::of(board, if strong_white{board.whites}else{board.blacks});
+    let weak = Side::of(board, if strong_white{board.blacks}else{board.whites});
+    let score = match endgame{
+        Endgame::InsufficientMaterial | Endgame::WrongBishop=>return Some(Score::DRAW),
+        Endgame::Kpk=>{
+            let pawn = square(strong.pawns.trailing_zeros());
+            if rank(pawn) == 0 || rank(pawn) == 7{
+                return None;
+            }
+            // everything is looked at from the pawns side, which moves up the board
+            let (mut own_king, mut other_king, mut pawn) = (strong.king, weak.king, pawn);
+            if !strong_white{
+                own_king ^= 56;
+                other_king ^= 56;
+                pawn ^= 56;
+            }
+            if file(pawn) > 3{
+                own_king ^= 7;
+                other_king ^= 7;
+                pawn ^= 7;
+            }
+            if !kpk_win(white == strong_white, own_king, other_king, pawn){
+                return Some(Score::DRAW);
+            }
+            KNOWN_WIN + 20*rank(pawn)
+        }
+        Endgame::Kbnk=>{
+            // the lone king can only be mated in a corner the bishop controls
+            let bishop_dark = dark(square(strong.bishops.trailing_zeros()));
+            let corners = if bishop_dark{[0, 63]}else{[7, 56]};
+            let corner_distance = corners.iter().map(|&corner| distance(weak.king, corner)).min().unwrap();
+            KNOWN_WIN + 100*(7-corner_distance) + 20*(7-distance(strong.king, weak.king))
+        }
+        Endgame::Kxk=>{
+            // drive the lone king to the edge and follow it with our own
+            let material = 900*strong.queens.count_ones() + 500*strong.rooks.count_ones()
+                + 300*(strong.bishops|strong.knights).count_ones();
+            KNOWN_WIN + material as i32 + 100*(3-edge_distance(weak.king)) + 20*(7-distance(strong.king, weak.king))
+        }
+    };
+    Some(Score::from_eval(if white == strong_white{score}else{-score}))
+}
+
+// kpk bitbase, white has the pawn on files a to d. one bit per position,
+// set when white wins with best play
+const KPK_SIZE:usize = 2*24*64*64;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Outcome{
+    Unknown,
+    Invalid,
+    Draw,
+    Win,
+}
+
+fn kpk_index(white_to_move:bool, white_king:usize, black_king:usize, pawn:usize)->usize{
+    let pawn_index = (rank(pawn) as usize - 1)*4 + file(pawn) as usize;
+    ((pawn_index*64 + white_king)*64 + black_king)*2 + white_to_move as usize
+}
+
+fn king_moves(from:usize)->impl Iterator<Item = usize>{
+    (0..64).filter(move |&to| distance(from, to) == 1)
+}
+
+fn pawn_attacks(pawn:usize, target:usize)->bool{
+    rank(target) == rank(pawn)+1 && (file(target)-file(pawn)).abs() == 1
+}
+
+fn kpk_initial(white_to_move:bool, white_king:usize, black_king:usize, pawn:usize)->Outcome{
+    if white_king == black_king || white_king == pawn || black_king == pawn || distance(white_king, black_king) <= 1{
+        return Outcome::Invalid;
+    }
+    if white_to_move{
+        if pawn_attacks(pawn, black_king){
+            return Outcome::Invalid;
+        }
+        // a safe promotion wins, the queen is either defended or out of reach
+        let promotion = pawn+8;
+        if rank(pawn) == 6 && promotion != white_king && promotion != black_king
+            && (distance(white_king, promotion) == 1 || distance(black_king, promotion) > 1){
+            return Outcome::Win;
+        }
+        return Outcome::Unknown;
+    }
+    if distance(black_king, pawn) == 1 && distance(white_king, pawn) > 1{
+        return Outcome::Draw;
+    }
+    let attacked = |target:usize| distance(target, white_king) <= 1 || pawn_attacks(pawn, target);
+    if king_moves(black_king).all(attacked){
+        return if pawn_attacks(pawn, black_king){Outcome::Win}else{Outcome::Draw};
+    }
+    Outcome::Unknown
+}
+
+fn kpk_classify(table:&[Outcome], white_to_move:bool, white_king:usize, black_king:usize, pawn:usize)->Outcome{
+    if white_to_move{
+        // white wins if any move wins, draws if every move draws
+        let mut all_draw = true;
+        let mut children:Vec<Outcome> = king_moves(white_king)
+            .filter(|&to| to != pawn && distance(to, black_king) > 1)
+            .map(|to| table[kpk_index(false, to, black_king, pawn)])
+            .collect();
+        let push = pawn+8;
+        if push != white_king && push != black_king{
+            if rank(pawn) == 6{
+                children.push(Outcome::Draw);   // an unsafe promotion, safe ones are wins already
+            }else{
+                children.push(table[kpk_index(false, white_king, black_king, push)]);
+                if rank(pawn) == 1 && push+8 != white_king && push+8 != black_king{
+                    children.push(table[kpk_index(false, white_king, black_king, push+8)]);
+                }
+            }
+        }
+        for child in children{
+            match child{
+                Outcome::Win=>return Outcome::Win,
+                Outcome::Draw=>{}
+                _=>all_draw = false,
+            }
+        }
+        return if all_draw{Outcome::Draw}else{Outcome::Unknown};
+    }
+    // black draws if any move draws, loses if every move loses
+    let mut all_win = true;
+    for to in king_moves(black_king){
+        if distance(to, white_king) <= 1 || pawn_attacks(pawn, to) || to == pawn{
+            continue;
+        }
+        match table[kpk_index(true, white_king, to, pawn)]{
+            Outcome::Draw=>return Outcome::Draw,
+            Outcome::Win=>{}
+            _=>all_win = false,
+        }
+    }
+    if all_win{Outcome::Win}else{Outcome::Unknown}
+}
+
+fn generate_kpk()->Vec<u64>{
+    // retrograde analysis: start from the positions decided by the rules and
+    // keep resolving the rest from their children until nothing changes.
+    // whatever is still open at the end cannot be forced, so it is a draw
+    let mut table = vec![Outcome::Unknown; KPK_SIZE];
+    let mut positions = Vec::with_capacity(KPK_SIZE);
+    for pawn_rank in 1..7{
+        for pawn_file in 0..4{
+            let pawn = pawn_rank*8 + pawn_file;
+            for white_king in 0..64{
+                for black_king in 0..64{
+                    for white_to_move in [false, true]{
+                        let index = kpk_index(white_to_move, white_king, black_king, pawn);
+                        table[index] = kpk_initial(white_to_move, white_king, black_king, pawn);
+                        if table[index] == Outcome::Unknown{
+                            positions.push((index, white_to_move, white_king, black_king, pawn));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    let mut changed = true;
+    while changed{
+        changed = false;
+        positions.retain(|&(index, white_to_move, white_king, black_king, pawn)|{
+            let outcome = kpk_classify(&table, white_to_move, white_king, black_king, pawn);
+            if outcome == Outcome::Unknown{
+                return true;
+            }
+            table[index] = outcome;
+            changed = true;
+            false
+        });
+    }
+    let mut bits = vec![0u64; KPK_SIZE.div_ceil(64)];
+    for (index, outcome) in table.iter().enumerate(){
+        if *outcome == Outcome::Win{
+            bits[index/64] |= 1<<(index%64);
+        }
+    }
+    bits
+}
+
+static KPK:OnceLock<Vec<u64>> = OnceLock::new();
+
+fn kpk_win(white_to_move:bool, white_king:usize, black_king:usize, pawn:usize)->bool{
+    // generated the first time a kpk position is evaluated
+    let bits = KPK.get_or_init(generate_kpk);
+    let index = kpk_index(white_to_move, white_king, black_king, pawn);
+    bits[index/64] & 1<<(index%64) != 0
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::notation::parse_fen;
+
+    fn score(fen:&str)->Option<Score>{
+        let (board, white) = parse_fen(fen).unwrap();
+        evaluate(&board, white)
+    }
+
+    fn won(fen:&str)->bool{
+        score(fen).unwrap() >= Score::from_eval(KNOWN_WIN)
+    }
+
+    #[test]
+    fn kpk_knows_its_wins_and_draws(){
+        // king in front of the pawn on the sixth wins with either side to move
+        assert!(won("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"));
+        assert_eq!(score("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap(), -score("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap());
+        // black to move is stalemated, white to move escorts the pawn in
+        assert!(won("4k3/4P3/4K3/8/8/8/8/8 w - - 0 1"));
+        assert_eq!(score("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"), Some(Score::DRAW));
+        // the defending king in front of a rook pawn
+        assert_eq!(score("k7/8/8/P7/8/8/8/7K w - - 0 1"), Some(Score::DRAW));
+        assert_eq!(score("k7/8/8/P7/8/8/8/7K b - - 0 1"), Some(Score::DRAW));
+        // the defending king is outside the square of the pawn
+        assert!(won("7k/8/8/P7/8/8/8/K7 w - - 0 1"));
+        assert!(-score("7k/8/8/P7/8/8/8/K7 b - - 0 1").unwrap() >= Score::from_eval(KNOWN_WIN));
+        // the same for black, mirrored and on the king side
+        assert!(won("K7/8/8/8/7p/8/8/k7 b - - 0 1"));
+        assert_eq!(score("k7/8/8/8/8/7p/8/7K w - - 0 1"), Some(Score::DRAW));
+    }
+
+    #[test]
+    fn drawn_material_is_recognised(){
+        for fen in ["4k3/8/8/8/8/8/8/3NK3 w - - 0 1", "4k3/8/8/8/8/8/8/2B1K3 b - - 0 1",
+            "4k3/8/8/8/8/8/8/2NNK3 w - - 0 1", "4kb2/8/8/8/8/8/8/2N1K3 w - - 0 1",
+            // a light squared bishop cannot take the dark h8 corner from the king
+            "7k/8/7P/8/8/8/8/1B2K3 w - - 0 1"]{
+            assert_eq!(score(fen), Some(Score::DRAW), "{}", fen);
+        }
+        assert_eq!(score("4k3/8/8/8/8/8/8/1BB1K3 w - - 0 1").map(|score| score > Score::from_eval(KNOWN_WIN)), Some(true));
+        assert_eq!(score("r3k3/8/8/8/8/8/8/R3K3 w - - 0 1"), None);
+    }
+
+    #[test]
+    fn kbnk_drives_the_king_to_the_bishops_corner(){
+        // a dark squared bishop mates in a1 or h8
+        let right = score("8/8/8/8/8/8/2K5/k1BN4 w - - 0 1").unwrap();
+        let wrong = score("8/8/8/8/8/8/5K2/2BN3k w - - 0 1").unwrap();
+        assert!(right > wrong && wrong > Score::from_eval(KNOWN_WIN));
+        assert_eq!(score("8/8/8/8/8/8/2K5/k1BN4 b - - 0 1").unwrap(), -right);
+    }
+
+    #[test]
+    fn kxk_drives_the_king_to_the_edge(){
+        let edge = score("7k/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let centre = score("8/8/8/4k3/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert!(edge > centre && centre > Score::from_eval(KNOWN_WIN));
+        // more material is a bigger win
+        assert!(score("8/8/8/4k3/8/8/8/Q3K3 w - - 0 1").unwrap() > centre);
+        assert_eq!(score("8/8/8/4k3/8/8/8/R3K3 b - - 0 1").unwrap(), -centre);
+    }
+}
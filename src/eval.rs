@@ -0,0 +1,492 @@
+// evaluation terms, all in centipawns
+// the tables are written from whites side with a8 in the top left corner,
+// the way a board diagram is read
+
+use std::sync::OnceLock;
+
+use crate::score::Score;
+use crate::{endgame, pawns, Board, ALLOWED_CAPTURE_LEFT, ALLOWED_CAPTURE_RIGHT};
+
+// every per piece array is indexed pawn, knight, bishop, rook, queen, king
+pub const MG_VALUES:[i32; 6] = [100, 320, 330, 500, 900, 0];
+pub const EG_VALUES:[i32; 6] = [120, 300, 320, 530, 940, 0];
+
+// a side with all its pieces counts 12 towards the phase, both sides 24
+pub const PHASE_WEIGHTS:[i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const MAX_PHASE:i32 = 24;
+
+#[rustfmt::skip]
+pub const MG_TABLES:[[i32; 64]; 6] = [
+    [   // pawn
+          0,   0,   0,   0,   0,   0,   0,   0,
+         50,  50,  50,  50,  50,  50,  50,  50,
+         10,  10,  20,  30,  30,  20,  10,  10,
+          5,   5,  10,  25,  25,  10,   5,   5,
+          0,   0,   0,  20,  20,   0,   0,   0,
+          5,  -5, -10,   0,   0, -10,  -5,   5,
+          5,  10,  10, -20, -20,  10,  10,   5,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    [   // knight
+        -50, -40, -30, -30, -30, -30, -40, -50,
+        -40, -20,   0,   0,   0,   0, -20, -40,
+        -30,   0,  10,  15,  15,  10,   0, -30,
+        -30,   5,  15,  20,  20,  15,   5, -30,
+        -30,   0,  15,  20,  20,  15,   0, -30,
+        -30,   5,  10,  15,  15,  10,   5, -30,
+        -40, -20,   0,   5,   5,   0, -20, -40,
+        -50, -40, -30, -30, -30, -30, -40, -50,
+    ],
+    [   // bishop
+        -20, -10, -10, -10, -10, -10, -10, -20,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,  10,  10,   5,   0, -10,
+        -10,   5,   5,  10,  10,   5,   5, -10,
+        -10,   0,  10,  10,  10,  10,   0, -10,
+        -10,  10,  10,  10,  10,  10,  10, -10,
+        -10,   5,   0,   0,   0,   0,   5, -10,
+        -20, -10, -10, -10, -10, -10, -10, -20,
+    ],
+    [   // rook
+          0,   0,   0,   0,   0,   0,   0,   0,
+          5,  10,  10,  10,  10,  10,  10,   5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+          0,   0,   0,   5,   5,   0,   0,   0,
+    ],
+    [   // queen
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,   5,   5,   5,   0, -10,
+         -5,   0,   5,   5,   5,   5,   0,  -5,
+          0,   0,   5,   5,   5,   5,   0,  -5,
+        -10,   5,   5,   5,   5,   5,   0, -10,
+        -10,   0,   5,   0,   0,   0,   0, -10,
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+    ],
+    [   // king, stay behind the pawns while there is material to attack it
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -20, -30, -30, -40, -40, -30, -30, -20,
+        -10, -20, -20, -20, -20, -20, -20, -10,
+         20,  20,   0,   0,   0,   0,  20,  20,
+         20,  30,  10,   0,   0,  10,  30,  20,
+    ],
+];
+
+#[rustfmt::skip]
+pub const EG_TABLES:[[i32; 64]; 6] = [
+    [   // pawn, the closer to promotion the better
+          0,   0,   0,   0,   0,   0,   0,   0,
+         60,  60,  60,  60,  60,  60,  60,  60,
+         40,  40,  40,  40,  40,  40,  40,  40,
+         25,  25,  25,  25,  25,  25,  25,  25,
+         15,  15,  15,  15,  15,  15,  15,  15,
+          5,   5,   5,   5,   5,   5,   5,   5,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    [   // knight
+        -50, -40, -30, -30, -30, -30, -40, -50,
+        -40, -20,   0,   0,   0,   0, -20, -40,
+        -30,   0,  10,  15,  15,  10,   0, -30,
+        -30,   5,  15,  20,  20,  15,   5, -30,
+        -30,   0,  15,  20,  20,  15,   0, -30,
+        -30,   5,  10,  15,  15,  10,   5, -30,
+        -40, -20,   0,   5,   5,   0, -20, -40,
+        -50, -40, -30, -30, -30, -30, -40, -50,
+    ],
+    [   // bishop
+        -20, -10, -10, -10, -10, -10, -10, -20,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,  10,  10,   5,   0, -10,
+        -10,   5,   5,  10,  10,   5,   5, -10,
+        -10,   5,  10,  10,  10,  10,   5, -10,
+        -10,   5,   5,  10,  10,   5,   5, -10,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -20, -10, -10, -10, -10, -10, -10, -20,
+    ],
+    [   // rook
+          5,   5,   5,   5,   5,   5,   5,   5,
+         10,  10,  10,  10,  10,  10,  10,  10,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    [   // queen
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+        -10,   0,   5,   5,   5,   5,   0, -10,
+        -10,   5,  10,  10,  10,  10,   5, -10,
+         -5,   5,  10,  15,  15,  10,   5,  -5,
+         -5,   5,  10,  15,  15,  10,   5,  -5,
+        -10,   5,  10,  10,  10,  10,   5, -10,
+        -10,   0,   5,   5,   5,   5,   0, -10,
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+    ],
+    [   // king, walk to the centre once the queens are gone
+        -50, -40, -30, -20, -20, -30, -40, -50,
+        -30, -20, -10,   0,   0, -10, -20, -30,
+        -30, -10,  20,  30,  30,  20, -10, -30,
+        -30, -10,  30,  40,  40,  30, -10, -30,
+        -30, -10,  30,  40,  40,  30, -10, -30,
+        -30, -10,  20,  30,  30,  20, -10, -30,
+        -30, -30,   0,   0,   0,   0, -30, -30,
+        -50, -30, -30, -30, -30, -30, -30, -50,
+    ],
+];
+
+pub const PIECE_NAMES:[&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
+
+// every tunable evaluation weight. the constants in this file and in pawns.rs
+// are the defaults, a parameter file or the tuner can replace them
+#[derive(Clone, PartialEq, Debug)]
+pub struct EvalParams{
+    pub mg_values:[i32; 6],
+    pub eg_values:[i32; 6],
+    pub mg_tables:[[i32; 64]; 6],
+    pub eg_tables:[[i32; 64]; 6],
+    pub mobility_mg:[i32; 6],
+    pub mobility_eg:[i32; 6],
+    pub pawn_shield:[i32; 2],
+    pub king_zone_weights:[i32; 6],
+    pub doubled:[i32; 2],       // pawn terms are (middlegame, endgame)
+    pub isolated:[i32; 2],
+    pub backward:[i32; 2],
+    pub passed_mg:[i32; 8],
+    pub passed_eg:[i32; 8],
+}
+
+pub const DEFAULT_PARAMS:EvalParams = EvalParams{
+    mg_values:MG_VALUES,
+    eg_values:EG_VALUES,
+    mg_tables:MG_TABLES,
+    eg_tables:EG_TABLES,
+    mobility_mg:MOBILITY_MG,
+    mobility_eg:MOBILITY_EG,
+    pawn_shield:PAWN_SHIELD,
+    king_zone_weights:KING_ZONE_WEIGHTS,
+    doubled:pawns::DOUBLED,
+    isolated:pawns::ISOLATED,
+    backward:pawns::BACKWARD,
+    passed_mg:pawns::PASSED_MG,
+    passed_eg:pawns::PASSED_EG,
+};
+
+impl EvalParams{
+    pub fn entries_mut(&mut self)->Vec<(String, &mut [i32])>{
+        // every weight as a named flat slice, in the order they are written to a file
+        let mut entries:Vec<(String, &mut [i32])> = vec![
+            ("mg_values".to_string(), &mut self.mg_values[..]),
+            ("eg_values".to_string(), &mut self.eg_values[..]),
+        ];
+        for (piece, table) in self.mg_tables.iter_mut().enumerate(){
+            entries.push((format!("mg_table_{}", PIECE_NAMES[piece]), &mut table[..]));
+        }
+        for (piece, table) in self.eg_tables.iter_mut().enumerate(){
+            entries.push((format!("eg_table_{}", PIECE_NAMES[piece]), &mut table[..]));
+        }
+        entries.extend([
+            ("mobility_mg".to_string(), &mut self.mobility_mg[..]),
+            ("mobility_eg".to_string(), &mut self.mobility_eg[..]),
+            ("pawn_shield".to_string(), &mut self.pawn_shield[..]),
+            ("king_zone_weights".to_string(), &mut self.king_zone_weights[..]),
+            ("doubled".to_string(), &mut self.doubled[..]),
+            ("isolated".to_string(), &mut self.isolated[..]),
+            ("backward".to_string(), &mut self.backward[..]),
+            ("passed_mg".to_string(), &mut self.passed_mg[..]),
+            ("passed_eg".to_string(), &mut self.passed_eg[..]),
+        ]);
+        entries
+    }
+
+    pub fn entries(&self)->Vec<(String, Vec<i32>)>{
+        let mut copy = self.clone();
+        copy.entries_mut().into_iter().map(|(name, values)| (name, values.to_vec())).collect()
+    }
+}
+
+static PARAMS:OnceLock<EvalParams> = OnceLock::new();
+
+pub fn params()->&'static EvalParams{
+    // the weights the engine evaluates with, the defaults unless set_params ran first
+    PARAMS.get_or_init(|| DEFAULT_PARAMS)
+}
+
+pub fn set_params(params:EvalParams)->Result<(), String>{
+    // must happen before the first position is evaluated, the incremental
+    // scores and the pawn hash assume the weights never change afterwards
+    PARAMS.set(params).map_err(|_| "evaluation parameters are already in use".to_string())
+}
+
+pub fn table_index(square:u32, white:bool)->usize{
+    // squares count from h1, the tables from a8. black reads them upside down
+    let rank = (square/8) as usize;
+    let file = 7 - (square%8) as usize;
+    if white{
+        return (7-rank)*8 + file;
+    }
+    rank*8 + file
+}
+
+pub fn piece_maps(board:&Board)->[u64; 6]{
+    [board.pawns, board.knights, board.bishops, board.rooks, board.queens, board.kings]
+}
+
+pub fn side_material_and_tables(board:&Board, p:&EvalParams, white:bool)->((i32, i32), (i32, i32)){
+    // material and table scores of one side, each as (middlegame, endgame)
+    let color_map = if white{board.whites}else{board.blacks};
+    let mut material = (0, 0);
+    let mut tables = (0, 0);
+    for (piece, piece_map) in piece_maps(board).iter().enumerate(){
+        let mut squares = piece_map & color_map;
+        while squares != 0{
+            let index = table_index(squares.trailing_zeros(), white);
+            material.0 += p.mg_values[piece];
+            material.1 += p.eg_values[piece];
+            tables.0 += p.mg_tables[piece][index];
+            tables.1 += p.eg_tables[piece][index];
+            squares &= squares-1;
+        }
+    }
+    (material, tables)
+}
+
+pub fn material_and_tables(board:&Board, p:&EvalParams)->(i32, i32){
+    // middlegame and endgame score, black minus white
+    let mut mg = 0;
+    let mut eg = 0;
+    for (piece, piece_map) in piece_maps(board).iter().enumerate(){
+        for (white, color_map, sign) in [(true, board.whites, -1), (false, board.blacks, 1)]{
+            let mut squares = piece_map & color_map;
+            while squares != 0{
+                let index = table_index(squares.trailing_zeros(), white);
+                mg += sign*(p.mg_values[piece] + p.mg_tables[piece][index]);
+                eg += sign*(p.eg_values[piece] + p.eg_tables[piece][index]);
+                squares &= squares-1;
+            }
+        }
+    }
+    (mg, eg)
+}
+
+fn square_score(board:&Board, p:&EvalParams, square:u32)->(i32, i32){
+    // material and table score of whatever stands on the square, black minus white.
+    // counted exactly like material_and_tables so the two always agree
+    let mask = 1u64<<square;
+    let mut mg = 0;
+    let mut eg = 0;
+    for (piece, piece_map) in piece_maps(board).iter().enumerate(){
+        for (white, color_map, sign) in [(true, board.whites, -1), (false, board.blacks, 1)]{
+            if piece_map & color_map & mask != 0{
+                let index = table_index(square, white);
+                mg += sign*(p.mg_values[piece] + p.mg_tables[piece][index]);
+                eg += sign*(p.eg_values[piece] + p.eg_tables[piece][index]);
+            }
+        }
+    }
+    (mg, eg)
+}
+
+pub fn table_delta(parent:&Board, child:&Board, p:&EvalParams)->(i32, i32){
+    // change of material_and_tables between two positions. a move touches
+    // two to four squares, so only those are rescored
+    let mut changed = (parent.whites^child.whites) | (parent.blacks^child.blacks);
+    for (before, after) in piece_maps(parent).iter().zip(piece_maps(child).iter()){
+        changed |= before^after;
+    }
+    let mut mg = 0;
+    let mut eg = 0;
+    while changed != 0{
+        let square = changed.trailing_zeros();
+        let (old_mg, old_eg) = square_score(parent, p, square);
+        let (new_mg, new_eg) = square_score(child, p, square);
+        mg += new_mg - old_mg;
+        eg += new_eg - old_eg;
+        changed &= changed-1;
+    }
+    (mg, eg)
+}
+
+pub fn game_phase(board:&Board)->i32{
+    // MAX_PHASE with all pieces on the board, 0 with only kings and pawns
+    let mut phase = 0;
+    for (piece, piece_map) in piece_maps(board).iter().enumerate(){
+        phase += PHASE_WEIGHTS[piece]*piece_map.count_ones() as i32;
+    }
+    phase.min(MAX_PHASE)
+}
+
+pub fn taper(mg:i32, eg:i32, phase:i32)->i32{
+    (mg*phase + eg*(MAX_PHASE-phase))/MAX_PHASE
+}
+
+pub fn blend(board:&Board, mg:i32, eg:i32, white:bool)->Score{
+    // tapers black minus white terms into a score for the side to move
+    let black_eval = taper(mg, eg, game_phase(board));
+    Score::from_eval(if white{-black_eval}else{black_eval})
+}
+
+pub fn evaluate(board:&Board, p:&EvalParams, white:bool)->Score{
+    // full recomputation with the given weights, no incremental scores or
+    // caches. evaluate_position gives the same result with the engine weights
+    if let Some(score) = endgame::evaluate(board, white){
+        return score;
+    }
+    let (mut mg, mut eg) = material_and_tables(board, p);
+    let (white_mg, white_eg) = pawns::side_pawn_structure(board, p, true);
+    let (black_mg, black_eg) = pawns::side_pawn_structure(board, p, false);
+    let (mobility_mg, mobility_eg) = mobility(board, p);
+    mg += black_mg - white_mg + mobility_mg + king_safety(board, p);
+    eg += black_eg - white_eg + mobility_eg;
+    blend(board, mg, eg, white)
+}
+
+// per attacked square that is not blocked by an own piece or covered by an
+// enemy pawn, counted from a typical number of such squares for the piece
+pub const MOBILITY_MG:[i32; 6] = [0, 4, 5, 2, 1, 0];
+pub const MOBILITY_EG:[i32; 6] = [0, 4, 5, 4, 2, 0];
+const MOBILITY_BASE:[i32; 6] = [0, 4, 6, 7, 13, 0];
+
+// own pawns on the first and second rank in front of the king
+pub const PAWN_SHIELD:[i32; 2] = [12, 6];
+// how much an attack on the king zone hurts, per piece type
+pub const KING_ZONE_WEIGHTS:[i32; 6] = [0, 2, 2, 3, 5, 0];
+// the squared attack weight is scaled down by this, so a lone attacker
+// barely counts and several together quickly become dangerous
+const KING_ZONE_DIVISOR:i32 = 4;
+
+fn pawn_attacks(pawns:u64, white:bool)->u64{
+    if white{
+        return (pawns<<9 & ALLOWED_CAPTURE_LEFT)|(pawns<<7 & ALLOWED_CAPTURE_RIGHT);
+    }
+    (pawns>>7 & ALLOWED_CAPTURE_LEFT)|(pawns>>9 & ALLOWED_CAPTURE_RIGHT)
+}
+
+pub fn side_mobility(board:&Board, p:&EvalParams, white:bool)->(i32, i32){
+    // middlegame and endgame score for one side
+    let (own, enemy) = if white{(board.whites, board.blacks)}else{(board.blacks, board.whites)};
+    let safe = !own & !pawn_attacks(board.pawns & enemy, !white);
+    let maps = piece_maps(board);
+    let mut mg = 0;
+    let mut eg = 0;
+    // knights, bishops, rooks and queens
+    for (piece, piece_map) in maps.iter().enumerate().take(5).skip(1){
+        let mut squares = piece_map & own;
+        while squares != 0{
+            let square = 1u64<<squares.trailing_zeros();
+            let moves = (board.piece_attacks(square) & safe).count_ones() as i32 - MOBILITY_BASE[piece];
+            mg += p.mobility_mg[piece]*moves;
+            eg += p.mobility_eg[piece]*moves;
+            squares &= squares-1;
+        }
+    }
+    (mg, eg)
+}
+
+pub fn mobility(board:&Board, p:&EvalParams)->(i32, i32){
+    // middlegame and endgame score, black minus white
+    let (white_mg, white_eg) = side_mobility(board, p, true);
+    let (black_mg, black_eg) = side_mobility(board, p, false);
+    (black_mg-white_mg, black_eg-white_eg)
+}
+
+pub fn side_king_safety(board:&Board, p:&EvalParams, white:bool)->i32{
+    // middlegame only, once the attackers are traded the king should be active
+    let (own, enemy) = if white{(board.whites, board.blacks)}else{(board.blacks, board.whites)};
+    let king = board.kings & own;
+    if king == 0{
+        return 0;
+    }
+    let zone = king | board.piece_attacks(king);
+
+    // the three files around the king, one and two ranks towards the enemy
+    let files = king | (king<<1 & ALLOWED_CAPTURE_LEFT) | (king>>1 & ALLOWED_CAPTURE_RIGHT);
+    let (first, second) = if white{(files<<8, files<<16)}else{(files>>8, files>>16)};
+    let own_pawns = board.pawns & own;
+    let shield = p.pawn_shield[0]*(own_pawns & first).count_ones() as i32
+        + p.pawn_shield[1]*(own_pawns & second).count_ones() as i32;
+
+    let maps = piece_maps(board);
+    let mut attack_weight = 0;
+    // knights, bishops, rooks and queens
+    for (piece, piece_map) in maps.iter().enumerate().take(5).skip(1){
+        let mut squares = piece_map & enemy;
+        while squares != 0{
+            let square = 1u64<<squares.trailing_zeros();
+            attack_weight += p.king_zone_weights[piece]*(board.piece_attacks(square) & zone).count_ones() as i32;
+            squares &= squares-1;
+        }
+    }
+    shield - attack_weight*attack_weight/KING_ZONE_DIVISOR
+}
+
+pub fn king_safety(board:&Board, p:&EvalParams)->i32{
+    // black minus white
+    side_king_safety(board, p, false) - side_king_safety(board, p, true)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::notation::{parse_fen, START_FEN};
+
+    fn mirror(fen:&str)->String{
+        // ranks flipped and colours swapped, so the other side has the same position
+        let fields:Vec<&str> = fen.split(' ').collect();
+        let ranks:Vec<String> = fields[0].split('/').rev().map(|rank| rank.chars().map(|c|
+            if c.is_ascii_uppercase(){c.to_ascii_lowercase()}else{c.to_ascii_uppercase()}).collect()).collect();
+        let side = if fields[1] == "w"{"b"}else{"w"};
+        format!("{} {} - - 0 1", ranks.join("/"), side)
+    }
+
+    #[test]
+    fn mirrored_positions_evaluate_the_same(){
+        for fen in [START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "4k3/8/8/3N4/8/8/8/R3K3 b - - 0 1"]{
+            let (board, white) = parse_fen(fen).unwrap();
+            let (mirrored, mirrored_white) = parse_fen(&mirror(fen)).unwrap();
+            assert_eq!(mirrored_white, !white);
+            assert_eq!(evaluate(&board, params(), white), evaluate(&mirrored, params(), mirrored_white), "{}", fen);
+            let (mg, eg) = material_and_tables(&board, params());
+            assert_eq!(material_and_tables(&mirrored, params()), (-mg, -eg), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn the_start_position_is_balanced_and_in_the_middlegame(){
+        let (board, white) = parse_fen(START_FEN).unwrap();
+        assert_eq!(material_and_tables(&board, params()), (0, 0));
+        assert_eq!(game_phase(&board), MAX_PHASE);
+        assert_eq!(evaluate(&board, params(), white), evaluate(&board, params(), !white));
+        let (pawn_ending, _) = parse_fen("4k3/pppp4/8/8/8/8/4PPPP/4K3 w - - 0 1").unwrap();
+        assert_eq!(game_phase(&pawn_ending), 0);
+    }
+
+    #[test]
+    fn the_taper_runs_from_middlegame_to_endgame(){
+        assert_eq!(taper(120, -40, MAX_PHASE), 120);
+        assert_eq!(taper(120, -40, 0), -40);
+        assert_eq!(taper(120, -40, MAX_PHASE/2), 40);
+    }
+
+    #[test]
+    fn tables_prefer_central_knights(){
+        let (rim, _) = parse_fen("4k3/8/8/8/8/8/8/N3K3 w - - 0 1").unwrap();
+        let (centre, _) = parse_fen("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1").unwrap();
+        // black minus white, so the better white knight gives the lower number
+        let (rim_mg, rim_eg) = material_and_tables(&rim, params());
+        let (centre_mg, centre_eg) = material_and_tables(&centre, params());
+        assert!(centre_mg < rim_mg && centre_eg < rim_eg);
+    }
+}
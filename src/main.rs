@@ -1,10 +1,36 @@
 extern crate colored;
 
-
-use std::thread::panicking;
+mod book;
+mod cli;
+mod endgame;
+mod eval;
+mod nnue;
+mod notation;
+mod params;
+mod pawns;
+mod pgn;
+mod random;
+mod score;
+mod search;
+mod skill;
+mod syzygy;
+mod trace;
+mod tt;
+mod tune;
+mod uci;
+mod xboard;
 
 use colored::*;
 use colored::ColoredString;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use notation::legal_moves;
+use params::Params;
+use book::OpeningBook;
+use score::Score;
+use search::{SearchLimits, SearchParams, SearchResult, SearchStats, Searcher};
+use skill::Skill;
+use tt::TranspositionTable;
 #[derive(Copy)]
 #[allow(dead_code)]
 struct Board{
@@ -18,10 +44,15 @@ struct Board{
     blacks:u64,         
     castelable_pieces:u64,  // bitmap of rooks and kings that can castle
     fifty_rule:u8,          // number of moves without capture of pawn push
-    en_passant_index:u8,    // index of piece susceptible to en passant
-    eval:i16,                // evaluation of the position
+    en_passant_index:u8,    // index of piece susceptible to en passant, 0 if there is none
+    eval:Score,             // evaluation of the position, for the side that was to move
+    mg:i32,                 // material and piece-square tables, black minus white,
+    eg:i32,                 // kept up to date as moves are made
 }
 
+// clone() is used to derive a child position from its parent, so the
+// en passant square of the parent is deliberately not carried over
+#[allow(clippy::non_canonical_clone_impl)]
 impl Clone for Board{
     fn clone(&self)->Board{
         Board {
@@ -36,7 +67,9 @@ impl Clone for Board{
             castelable_pieces: self.castelable_pieces, 
             fifty_rule: self.fifty_rule, 
             en_passant_index: 0, 
-            eval: self.eval 
+            eval: self.eval,
+            mg: self.mg,
+            eg: self.eg,
         }
     }
 }
@@ -56,6 +89,7 @@ impl<T> OffsetClosure<T>
 where
     T:Fn(u64, u64)->u64
 {
+    #[allow(clippy::redundant_field_names)]
     const fn new(closure:T, usage_mask:u64)->Self{
         Self{closure:closure, usage_mask:usage_mask}
     }
@@ -76,14 +110,13 @@ abcdefgh
 
 
 
-const MOVE_SEARCH_DEPTH:i32 = 2;
-const CENTER_PIECES:u64 = 103481868288;
 const BLACK_PAWN_HOMEROW:u64 = 71776119061217280;
 const WHITE_PAWN_HOMEROW:u64 = 65280;
+const PROMOTION_ROWS:u64 = 18374686479671623935;
 const ALLOWED_CAPTURE_LEFT:u64 = 18374403900871474942;
 const ALLOWED_CAPTURE_RIGHT:u64 = 9187201950435737471;
 const KNIGHT_OFFSETS:[KnOfst;4] = [
-    KnOfst{offset:17, usage_mask_p:18446603888132915328, usage_mask_n:723401728381419519},
+    KnOfst{offset:17, usage_mask_p:18446603888132915328, usage_mask_n:72340172838141951},
     KnOfst{offset:15, usage_mask_p:18446463702556279041, usage_mask_n:9259542123273846783},
     KnOfst{offset:10, usage_mask_p:18428941609300181184, usage_mask_n:217020518514230271},
     KnOfst{offset: 6, usage_mask_p:18375534216072069891, usage_mask_n:13889313184910721279},
@@ -91,6 +124,7 @@ const KNIGHT_OFFSETS:[KnOfst;4] = [
 ];
 
 
+#[allow(clippy::identity_op)]
 const CLOSURES:[fn(u64, u64)->u64; 8] = [
     |x,y| x<<(9*y),
     |x,y| x<<(7*y),
@@ -102,6 +136,7 @@ const CLOSURES:[fn(u64, u64)->u64; 8] = [
     |x,y| x>>(8*y),
 ];
 
+#[allow(clippy::type_complexity)]
 const SHIFTING_CLOSURES:[OffsetClosure<fn(u64, u64)->u64>; 8] = [
     OffsetClosure::new(CLOSURES[0], 72340172838076673),
     OffsetClosure::new(CLOSURES[1], 9259542123273814144),
@@ -117,7 +152,7 @@ const SHIFTING_CLOSURES:[OffsetClosure<fn(u64, u64)->u64>; 8] = [
 #[allow(dead_code)]
 impl Board{
     fn new()->Board{
-        Board {
+        let mut board = Board {
             kings: 576460752303423496,
             queens: 1152921504606846992,
             rooks: 9295429630892703873, 
@@ -129,26 +164,50 @@ impl Board{
             castelable_pieces:9871890383196127369,
             fifty_rule:0,
             en_passant_index:0,
-            eval:0,
-        }
+            eval:Score::DRAW,
+            mg:0,
+            eg:0,
+        };
+        (board.mg, board.eg) = eval::material_and_tables(&board, eval::params());
+        board
     }
 
-    fn evaluate_position(&mut self)->i16{
-        let eval;
-
-        let pawn = (self.pawns&self.blacks).count_ones() as i16 - (self.pawns&self.whites).count_ones() as i16;
-        let knight = 3*((self.knights&self.blacks).count_ones() as i16 - (self.knights&self.whites).count_ones() as i16);
-        let bishop = 3*((self.bishops&self.blacks).count_ones() as i16 - (self.bishops&self.whites).count_ones() as i16);
-        let rook = 5*((self.rooks&self.blacks).count_ones() as i16 - (self.rooks&self.whites).count_ones() as i16);
-        let queen = 9*((self.queens&self.blacks).count_ones() as i16 - (self.queens&self.whites).count_ones() as i16);
-        let king = 200*((self.kings&self.blacks).count_ones() as i16 - (self.kings&self.whites).count_ones() as i16);
-        let centre = (self.pawns&self.blacks&CENTER_PIECES).count_ones() as i16 - (self.pawns&self.whites&CENTER_PIECES).count_ones() as i16;
-        eval = pawn+knight+bishop+rook+queen+king+centre;
+    fn evaluate_position(&mut self, white:bool)->Score{
+        // material, piece-square tables, pawn structure, mobility and king
+        // safety for the middlegame and the endgame, blended by how much
+        // material is left. endgames with a specialised evaluator use that instead.
+        // the terms are black minus white, the score is for the side to move
+        let params = eval::params();
+        debug_assert_eq!((self.mg, self.eg), eval::material_and_tables(self, params), "incremental material and tables out of sync");
+        if let Some(eval) = endgame::evaluate(self, white){
+            self.eval = eval;
+            return eval;
+        }
+        let (mut mg, mut eg) = (self.mg, self.eg);
+        let (pawns_mg, pawns_eg) = pawns::pawn_structure(self);
+        let (mobility_mg, mobility_eg) = eval::mobility(self, params);
+        mg += pawns_mg + mobility_mg + eval::king_safety(self, params);
+        eg += pawns_eg + mobility_eg;
+        let eval = eval::blend(self, mg, eg, white);
         self.eval = eval;
-        return eval;   
+        eval
     }
 
 
+    fn update_tables(&mut self, parent:&Board){
+        // make step for the incremental material and tables, the fifty move counter
+        // and the castling rights, called on every position derived from parent.
+        // unmaking is free since the parent is kept
+        let (mg, eg) = eval::table_delta(parent, self, eval::params());
+        self.mg = parent.mg + mg;
+        self.eg = parent.eg + eg;
+        let capture = (self.whites|self.blacks).count_ones() < (parent.whites|parent.blacks).count_ones();
+        self.fifty_rule = if capture || parent.pawns & !self.pawns != 0{0}else{parent.fifty_rule.saturating_add(1)};
+        // a king or rook that moves or is taken loses its right, either way its square changes
+        let changed = (parent.whites^self.whites) | (parent.blacks^self.blacks);
+        self.castelable_pieces = parent.castelable_pieces & !changed;
+    }
+
     fn take(&mut self, bitmap:u64){
         if self.blacks & bitmap != 0{
             self.blacks ^= bitmap;
@@ -171,174 +230,145 @@ impl Board{
         }
     }
 
-}
-
+    fn promote_pawns(&mut self){
+        // pawns that reached the last rank become queens
+        let promoted = self.pawns & PROMOTION_ROWS;
+        self.pawns ^= promoted;
+        self.queens |= promoted;
+    }
 
+    fn attacks(&self, white:bool)->u64{
+        // bitmap of every square the given color attacks
+        // sliders are raycast with the same closures the move generator uses
+        let color_map = if white{self.whites}else{self.blacks};
+        let mut attacks = 0;
 
-#[inline]
-fn compare_boards(best_board:&mut Option<Board>, new_board:&mut Board, depth:i32){
-    //println!("compare_boards()");
-    let eval;
-    if depth != 0{
-        let best_response = find_best_response(*new_board); 
-        eval = find_best_move(best_response, depth-1).eval; //evaluation happens by magic
-        new_board.eval = eval; 
-    }else{
-        eval = new_board.evaluate_position();
-    }
-    match best_board{
-        None=>{*best_board = Some(*new_board)},
-        Some(ref prev_board)=>{
-            if eval >= prev_board.eval{
-                *best_board = Some(*new_board);    
-            }
+        let pawns = self.pawns & color_map;
+        if white{
+            attacks |= (pawns<<9 & ALLOWED_CAPTURE_LEFT)|(pawns<<7 & ALLOWED_CAPTURE_RIGHT);
+        }else{
+            attacks |= (pawns>>7 & ALLOWED_CAPTURE_LEFT)|(pawns>>9 & ALLOWED_CAPTURE_RIGHT);
         }
-    }
-}
 
-#[inline]
-fn compare_boards_negative(best_board:&mut Option<Board>, new_board:&mut Board){
-    //println!("compare_boards_negative()");
-    //let best_response = find_best_response(*new_board); 
-
-    // let eval = find_best_move(best_response, depth-1).eval; 
-    // I might add small depth to the response search later
-
-    let eval = new_board.evaluate_position();
-    new_board.eval = eval; 
-    match best_board{
-    None=>{*best_board = Some(*new_board)},
-    Some(ref prev_board)=>{
-        if eval < prev_board.eval{
-        *best_board = Some(*new_board);    
+        let mut pieces = (self.knights|self.bishops|self.rooks|self.queens|self.kings) & color_map;
+        while pieces != 0{
+            let piece = 1<<pieces.ilog2();
+            attacks |= self.piece_attacks(piece);
+            pieces ^= piece;
         }
-    }}
-}
-
-
-fn find_best_move(board:Board,  depth:i32)->Board{
-    // the functions job is to take in results from the same functions one
-    // layer above and compare them one at a time
-    // my first intuition is to use while let Some() for all of it
-    // the challange is not checking the same position twize without      
-    // storing every checked position on the heap. 
-
-    let mut best_board:Option<Board> = None;
-    println!("starting pawn search layer {}", depth);
-    let mut pawn_bitmap:u64 = 0;
-    while let Some(mut new_board) = find_new_pawn_move(&board, &mut pawn_bitmap){
-        compare_boards(&mut best_board, &mut new_board, depth)
-    }
-    
-    println!("starting knight search layer {}", depth);
-    let mut knight_bitmap:u64 = 0;
-    while let Some(mut new_board) = find_new_knight_move(&board, &mut knight_bitmap, board.blacks){
-        compare_boards(&mut best_board, &mut new_board, depth);
+        attacks
     }
 
-    println!("starting bishop search layer {}", depth);
-    let mut bishop_bitmap:u64 = 0;
-    while let Some(mut new_board) = find_new_bishop_move(&board, &mut bishop_bitmap, board.blacks){
-        compare_boards(&mut best_board, &mut new_board, depth);
+    fn piece_attacks(&self, piece:u64)->u64{
+        // squares a single knight, bishop, rook, queen or king attacks
+        let occupied = self.whites|self.blacks;
+        if piece & self.knights != 0{
+            let mut attacks = 0;
+            for kn_ofst in KNIGHT_OFFSETS{
+                if piece & kn_ofst.usage_mask_p == 0{
+                    attacks |= piece<<kn_ofst.offset;
+                }
+                if piece & kn_ofst.usage_mask_n == 0{
+                    attacks |= piece>>kn_ofst.offset;
+                }
+            }
+            return attacks;
+        }else if piece & self.bishops != 0{
+            return ray_attacks(piece, occupied, &[0,1,2,3], 7);
+        }else if piece & self.rooks != 0{
+            return ray_attacks(piece, occupied, &[4,5,6,7], 7);
+        }else if piece & self.queens != 0{
+            return ray_attacks(piece, occupied, &[0,1,2,3,4,5,6,7], 7);
+        }else if piece & self.kings != 0{
+            return ray_attacks(piece, occupied, &[0,1,2,3,4,5,6,7], 1);
+        }
+        0
     }
 
-    println!("starting rook search layer {}", depth);
-    let mut rook_bitmap:u64 = 0;
-    while let Some(mut new_board) = find_new_rook_move(&board, &mut rook_bitmap, board.blacks){
-        //print_mask(new_board.rooks, "rooks");
-        compare_boards(&mut best_board, &mut new_board, depth);
+    fn in_check(&self, white:bool)->bool{
+        let color_map = if white{self.whites}else{self.blacks};
+        self.kings & color_map & self.attacks(!white) != 0
     }
 
-    println!("starting queen search layer {}", depth);
-    let mut queen_bitmap:u64 = 0;
-    while let Some(mut new_board) = find_new_queen_move(&board, &mut queen_bitmap, board.blacks){
-        compare_boards(&mut best_board, &mut new_board, depth);
-    }
-    println!("starting king search layer {}", depth);
-    let mut king_bitmap:u64 = 0;
-    while let Some(mut new_board) = find_new_king_move(&board, &mut king_bitmap, board.blacks){
-        compare_boards(&mut best_board, &mut new_board, depth);
-    }
-    println!("finished layer {}", depth);
-    let return_board = best_board.unwrap();
-    if return_board.whites & return_board.blacks != 0{
-        display_board_windows(&board, 0);
-        print_mask(board.whites, "old whites");
-        print_mask(board.blacks, "old blacks");
-        display_board_windows(&return_board, 0);
-        print_mask(return_board.whites, "new whites");
-        print_mask(return_board.blacks, "new blacks");
-        panic!("we messed up");
-    }
-    return return_board;
 }
 
-fn find_best_response(board:Board)->Board{
-    //println!("find_best_response()");
-    // this funciton does the same shit as the find_best_move()
-    // but its from white, and searches for the move thats in whites favour
-    // compare_boards_negative() finds the "worst" move, from the engines pov
-
-    let mut best_board:Option<Board> = None;
-
-    let mut pawn_bitmap:u64 = 0;
-    println!("starting pawn response search layer");
-    while let Some(mut new_board) = find_new_white_pawn_move(&board, &mut pawn_bitmap){
-        //println!("found_pawn_move()");
-        compare_boards_negative(&mut best_board, &mut new_board);
-    }
-
-    println!("starting knight response search layer");
-    let mut knight_bitmap:u64 = 0;
-    while let Some(mut new_board) = find_new_knight_move(&board, &mut knight_bitmap, board.whites){
-        //println!("found_knight_move()");
-        compare_boards_negative(&mut best_board, &mut new_board);
+fn ray_attacks(piece:u64, occupied:u64, closure_indexes:&[usize], max_steps:u64)->u64{
+    // walks every given direction until it leaves the board or hits a piece,
+    // the blocking square itself is included
+    let mut attacks = 0;
+    for &closure_index in closure_indexes{
+        let offset = &SHIFTING_CLOSURES[closure_index];
+        for offset_scalars in 1..=max_steps{
+            let moved_piece = (offset.closure)(piece, offset_scalars);
+            if moved_piece == 0 || moved_piece & offset.usage_mask != 0{
+                break;
+            }
+            attacks |= moved_piece;
+            if moved_piece & occupied != 0{
+                break;
+            }
+        }
     }
+    attacks
+}
 
-    println!("starting bishop response search layer");
-    let mut bishop_bitmap:u64 = 0;
-    while let Some(mut new_board) = find_new_bishop_move(&board, &mut bishop_bitmap, board.whites){
-        //print_mask(new_board.bishops, "new bishops");
-        compare_boards_negative(&mut best_board, &mut new_board);
-    }
 
-    println!("starting rook response search layer");
-    let mut rook_bitmap:u64 = 0;
-    while let Some(mut new_board) = find_new_rook_move(&board, &mut rook_bitmap, board.whites){
-        //println!("found_rook_move()");
-        compare_boards_negative(&mut best_board, &mut new_board);
-    }
 
-    println!("starting queen response search layer");
-    let mut queen_bitmap:u64 = 0;
-    while let Some(mut new_board) = find_new_queen_move(&board, &mut queen_bitmap, board.whites){
-        //println!("found_queen_move()");
-        compare_boards_negative(&mut best_board, &mut new_board);
+fn find_best_move(board:Board, white:bool, limits:&SearchLimits, params:&SearchParams, tt:&TranspositionTable, stop:&AtomicBool, report:Option<&mut dyn FnMut(&SearchResult)>)->SearchResult{
+    // lazy smp: every thread runs its own iterative deepening on the same
+    // position and they only cooperate through the shared transposition table.
+    // the main thread decides when to stop and its result is the one played.
+    // setting stop from outside ends the search early, the protocols use it.
+    // below full strength a single thread searches a few shallow lines to pick from
+    let start = Instant::now();
+    let skill = Skill::new(params);
+    let (mut weakened, mut weakened_limits) = (*params, *limits);
+    if let Some(skill) = &skill{
+        weakened.threads = 1;
+        weakened.multi_pv = weakened.multi_pv.max(skill::LINES);
+        weakened_limits.depth = limits.depth.min(skill.depth());
     }
-
-    println!("starting king response search layer");
-    let mut king_bitmap:u64 = 0;
-    while let Some(mut new_board) = find_new_king_move(&board, &mut king_bitmap, board.whites){
-        //println!("found_king_move()");
-        compare_boards_negative(&mut best_board, &mut new_board);
+    let (params, limits) = (&weakened, &weakened_limits);
+
+    let (best, helper_stats) = std::thread::scope(|scope|{
+        let helpers:Vec<_> = (1..params.threads.max(1)).map(|thread_index|{
+            scope.spawn(move ||{
+                let mut searcher = Searcher::new(params, tt, stop);
+                searcher.iterative_deepening(&board, white, limits, thread_index, None);
+                searcher.stats
+            })
+        }).collect();
+
+        let mut searcher = Searcher::new(params, tt, stop);
+        searcher.deadline = limits.movetime.map(|movetime| start+movetime);
+        searcher.node_limit = skill.as_ref().map(Skill::nodes);
+        let best = searcher.iterative_deepening(&board, white, limits, 0, report);
+        stop.store(true, Ordering::Relaxed);
+        let helper_stats:Vec<SearchStats> = helpers.into_iter().map(|helper| helper.join().unwrap()).collect();
+        (best, helper_stats)
+    });
+
+    let mut result = best.unwrap_or_else(||{
+        // stopped before the first iteration finished, there still has to be a move
+        let never = AtomicBool::new(false);
+        let depth_one = SearchLimits{depth:1, movetime:None};
+        Searcher::new(params, tt, &never).iterative_deepening(&board, white, &depth_one, 0, None).unwrap()
+    });
+    for stats in &helper_stats{
+        result.stats.merge(stats);
     }
-
-    let return_board = best_board.unwrap();
+    result.stats.time = start.elapsed();
+    let return_board = result.board;
     if return_board.whites & return_board.blacks != 0{
-        display_board_windows(&board, 0);
+        display_board_windows(&board, 0, true);
         print_mask(board.whites, "old whites");
         print_mask(board.blacks, "old blacks");
-        print_mask(board.pawns, "old pawns");
-        print_mask(board.queens, "old queen");
-        display_board_windows(&return_board, 0);
+        display_board_windows(&return_board, 0, true);
         print_mask(return_board.whites, "new whites");
         print_mask(return_board.blacks, "new blacks");
-        print_mask(return_board.pawns, "new pawns");
-        print_mask(return_board.queens, "new queen");
         panic!("we messed up");
     }
-    
-    return return_board;
+    result
 }
 
 /*
@@ -383,17 +413,19 @@ abcdefgh
    // board.blacks = 18441959067824947200;
     board.whites = 68719542255;
     let mut pawn_bitmap:u64 = 0;
-    display_board_windows(&board, 1);
+    display_board_windows(&board, 1, true);
     std::thread::sleep(std::time::Duration::from_secs(2));
    while let Some(new_board) = find_new_queen_move(&board, &mut pawn_bitmap, board.whites){
-        display_board_windows(&new_board, 0);
+        display_board_windows(&new_board, 0, true);
     }
 }*/
 
+#[allow(clippy::assign_op_pattern, clippy::clone_on_copy)]
 fn find_new_pawn_move(board:&Board, pawn_bitmap:&mut u64)->Option<Board>{
     // find a move that has not happened yet
     // tick the bitmap
 
+    let occupied = board.whites|board.blacks;
     let mut pawns = board.pawns & board.blacks;
     while pawns != 0{
         let first_pawn = 1<<pawns.ilog2();
@@ -405,13 +437,14 @@ fn find_new_pawn_move(board:&Board, pawn_bitmap:&mut u64)->Option<Board>{
         let take_r = first_pawn>>9;
         let pushed_pawn = first_pawn>>8;
         let jumped_pawn = first_pawn>>16;
-        if pushed_pawn & *pawn_bitmap | (board.whites&pushed_pawn)  == 0{
+        if pushed_pawn & *pawn_bitmap | (occupied&pushed_pawn)  == 0{
             *pawn_bitmap = *pawn_bitmap|pushed_pawn;
             let mut board_copy = board.clone();
             board_copy.pawns ^= first_pawn|pushed_pawn  ;
             board_copy.blacks ^= first_pawn|pushed_pawn;
+            board_copy.promote_pawns();
             return Some(board_copy);
-        }else if jumped_pawn & *pawn_bitmap | (board.whites&jumped_pawn) == 0 && first_pawn & BLACK_PAWN_HOMEROW != 0{
+        }else if jumped_pawn & *pawn_bitmap | (occupied&(pushed_pawn|jumped_pawn)) == 0 && first_pawn & BLACK_PAWN_HOMEROW != 0{
             *pawn_bitmap = *pawn_bitmap|jumped_pawn;
             let mut board_copy = board.clone();
             board_copy.pawns = board.pawns^first_pawn|jumped_pawn;
@@ -423,6 +456,7 @@ fn find_new_pawn_move(board:&Board, pawn_bitmap:&mut u64)->Option<Board>{
             board_copy.take(take_l);
             board_copy.pawns ^= first_pawn|take_l;
             board_copy.blacks ^= first_pawn|take_l;
+            board_copy.promote_pawns();
             return Some(board_copy);
         }else if take_r & (board.whites & !*pawn_bitmap & ALLOWED_CAPTURE_RIGHT) != 0{
             *pawn_bitmap = *pawn_bitmap|take_r;
@@ -430,8 +464,9 @@ fn find_new_pawn_move(board:&Board, pawn_bitmap:&mut u64)->Option<Board>{
             board_copy.take(take_r);
             board_copy.pawns ^= first_pawn|take_r;
             board_copy.blacks ^= first_pawn|take_r;
+            board_copy.promote_pawns();
             return Some(board_copy);
-        }else if (take_l | take_r) & 1<<board.en_passant_index & board.whites != 0{
+        }else if board.en_passant_index != 0 && (take_l | take_r) & 1<<board.en_passant_index & board.whites != 0{
             *pawn_bitmap |= 1<<board.en_passant_index;
             let mut board_copy = board.clone();
             board_copy.take(1<<(board.en_passant_index-8));
@@ -446,14 +481,16 @@ fn find_new_pawn_move(board:&Board, pawn_bitmap:&mut u64)->Option<Board>{
         }
     }
 
-    return None;
+    None
 }
 
+#[allow(clippy::clone_on_copy)]
 fn find_new_white_pawn_move(board:&Board, pawn_bitmap:&mut u64)->Option<Board>{
     //println!("find_new_white_pawn_move()");
     // find a move that has not happened yet
     // tick the bitmap
 
+    let occupied = board.whites|board.blacks;
     let mut pawns = board.pawns & board.whites;
     while pawns != 0{
         let first_pawn = 1<<pawns.ilog2();
@@ -465,13 +502,14 @@ fn find_new_white_pawn_move(board:&Board, pawn_bitmap:&mut u64)->Option<Board>{
         let take_r = first_pawn<<7;
         let pushed_pawn = first_pawn<<8;
         let jumped_pawn = first_pawn<<16;
-        if pushed_pawn&(*pawn_bitmap) | (board.blacks&pushed_pawn) == 0 {
+        if pushed_pawn&(*pawn_bitmap) | (occupied&pushed_pawn) == 0 {
             *pawn_bitmap |= pushed_pawn;
             let mut board_copy = board.clone();
             board_copy.pawns ^= first_pawn|pushed_pawn;
             board_copy.whites ^= first_pawn|pushed_pawn;
+            board_copy.promote_pawns();
             return Some(board_copy);
-        }else if (jumped_pawn & *pawn_bitmap) | (board.blacks&jumped_pawn) == 0 && first_pawn & WHITE_PAWN_HOMEROW != 0{
+        }else if (jumped_pawn & *pawn_bitmap) | (occupied&(pushed_pawn|jumped_pawn)) == 0 && first_pawn & WHITE_PAWN_HOMEROW != 0{
             *pawn_bitmap |= jumped_pawn;
             let mut board_copy = board.clone();
             board_copy.pawns ^= first_pawn|jumped_pawn;
@@ -483,6 +521,7 @@ fn find_new_white_pawn_move(board:&Board, pawn_bitmap:&mut u64)->Option<Board>{
             board_copy.take(take_l);
             board_copy.pawns ^= first_pawn|take_l;
             board_copy.whites ^= first_pawn|take_l;
+            board_copy.promote_pawns();
             return Some(board_copy);
         }else if take_r & (board.blacks & !*pawn_bitmap & ALLOWED_CAPTURE_RIGHT) != 0{
             *pawn_bitmap |= take_r;
@@ -490,8 +529,9 @@ fn find_new_white_pawn_move(board:&Board, pawn_bitmap:&mut u64)->Option<Board>{
             board_copy.take(take_r);
             board_copy.pawns ^= first_pawn|take_r;
             board_copy.whites ^= first_pawn|take_r;
+            board_copy.promote_pawns();
             return Some(board_copy);
-        }else if (take_l | take_r) & 1<<board.en_passant_index & board.blacks != 0{
+        }else if board.en_passant_index != 0 && (take_l | take_r) & 1<<board.en_passant_index & board.blacks != 0{
             *pawn_bitmap |= 1<<board.en_passant_index;
             let mut board_copy = board.clone();
             board_copy.take(1<<(board.en_passant_index+8));
@@ -506,9 +546,10 @@ fn find_new_white_pawn_move(board:&Board, pawn_bitmap:&mut u64)->Option<Board>{
         }
     }
 
-    return None;
+    None
 }
 
+#[allow(clippy::assign_op_pattern, clippy::clone_on_copy)]
 fn find_new_knight_move(board:&Board, knight_bitmap:&mut u64, color_map:u64)->Option<Board>{
     /*
     01010
@@ -568,13 +609,19 @@ fn find_new_knight_move(board:&Board, knight_bitmap:&mut u64, color_map:u64)->Op
         *knight_bitmap = (*knight_bitmap|first_knight) & board.knights & color_map;
     }
 
-    return new_board;
+    new_board
 }
 
 fn cross_positive(first_piece:u64, pieces:&mut u64, piece_bitmap:&mut u64, color_map:u64, rev_color:u64, offset:&OffsetClosure<fn(u64, u64)->u64>)->Option<u64>{
     for offset_scalars in 1..8{
         let moved_piece = (offset.closure)(first_piece, offset_scalars);
         //print_mask(moved_piece, "crossed piece");
+        // own pieces stop the ray before the bitmap is looked at, it also holds
+        // the squares of the pieces of this kind that are done
+        if (moved_piece & color_map != 0)||(moved_piece == 0)||(moved_piece & offset.usage_mask != 0){
+            //println!("hitttt");
+            break;
+        }
         if moved_piece & (*piece_bitmap & rev_color) != 0{
             //print_mask(rev_color, "rev color");
             //println!("first broken");
@@ -585,10 +632,6 @@ fn cross_positive(first_piece:u64, pieces:&mut u64, piece_bitmap:&mut u64, color
             //println!("continued");
             continue;
         }
-        if (moved_piece & color_map != 0)||(moved_piece == 0)||(moved_piece & offset.usage_mask != 0){
-            //println!("hitttt");
-            break;
-        } 
         *pieces ^= first_piece|moved_piece;
         *piece_bitmap |= moved_piece;
         //println!("returned Some");
@@ -596,9 +639,10 @@ fn cross_positive(first_piece:u64, pieces:&mut u64, piece_bitmap:&mut u64, color
         return Some(moved_piece);
     }
     //println!("returned None");
-    return None;
+    None
 }
 
+#[allow(clippy::assign_op_pattern, clippy::clone_on_copy)]
 fn find_new_bishop_move(board:&Board, bishop_bitmap:&mut u64, color_map:u64)->Option<Board>{
     // fancy schmancy raycasting
     let mut new_board:Option<Board> = None;
@@ -638,9 +682,10 @@ fn find_new_bishop_move(board:&Board, bishop_bitmap:&mut u64, color_map:u64)->Op
         *bishop_bitmap = (*bishop_bitmap|first_bishop) & board.bishops & color_map;
     }
     //print_mask(new_board.unwrap().bishops, "new bishops");
-    return new_board;
+    new_board
 }
 
+#[allow(clippy::assign_op_pattern, clippy::clone_on_copy)]
 fn find_new_rook_move(board:&Board, rook_bitmap:&mut u64, color_map:u64)->Option<Board>{
     // fancy schmancy raycasting
     let mut new_board:Option<Board> = None;
@@ -681,9 +726,10 @@ fn find_new_rook_move(board:&Board, rook_bitmap:&mut u64, color_map:u64)->Option
         *rook_bitmap = (*rook_bitmap|first_rook) & board.rooks & color_map;
     }
 
-    return new_board;
+    new_board
 }
 
+#[allow(clippy::assign_op_pattern, clippy::clone_on_copy)]
 fn find_new_queen_move(board:&Board, queen_bitmap:&mut u64, color_map:u64)->Option<Board>{
     // fancy schmancy raycasting
     let mut new_board:Option<Board> = None;
@@ -694,7 +740,6 @@ fn find_new_queen_move(board:&Board, queen_bitmap:&mut u64, color_map:u64)->Opti
         let first_queen = 1<<queens.ilog2() as u64;
         if first_queen & *queen_bitmap != 0{
             queens ^= first_queen;
-            *queen_bitmap = *queen_bitmap & board.queens;
             continue;
         }
 
@@ -719,7 +764,7 @@ fn find_new_queen_move(board:&Board, queen_bitmap:&mut u64, color_map:u64)->Opti
         *queen_bitmap = (*queen_bitmap|first_queen) & board.queens & color_map;
     }
 
-    return new_board;
+    new_board
 }
 
 fn kings_cross_positive(first_piece:u64, pieces:&mut u64, piece_bitmap:&mut u64, color_map:u64, offset:&OffsetClosure<fn(u64,u64)->u64>)->Option<u64>{
@@ -733,9 +778,10 @@ fn kings_cross_positive(first_piece:u64, pieces:&mut u64, piece_bitmap:&mut u64,
     }
     *pieces ^= first_piece|moved_piece;
     *piece_bitmap |= moved_piece;
-    return Some(moved_piece);
+    Some(moved_piece)
 }
 
+#[allow(clippy::clone_on_copy)]
 fn find_new_king_move(board:&Board, king_bitmap:&mut u64, color_map:u64)->Option<Board>{
     let mut kings = board.kings & color_map;
     //print_mask(kings, "kings");
@@ -769,34 +815,156 @@ fn find_new_king_move(board:&Board, king_bitmap:&mut u64, color_map:u64)->Option
         *king_bitmap = (*king_bitmap|first_king) & board.kings & color_map;
     }
     //print_mask(new_board.unwrap().kings, "new king move");
-    return None;
+    None
 }
 
 
 
-fn main() {
-
-
-    println!("let the chess begin");
-
-
-    let mut board = Board::new();
-    print_mask(board.rooks, "rookie smookies");
-
+fn exit_on_error(result:Result<(), String>){
+    if let Err(error) = result{
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
 
-    while &board.kings.count_ones()==&2{ //yes this game lets you capture the kings before the game ends
-        display_board_windows(&board, 0);
-        
-        board = collect_white_move(board);
-        display_board_windows(&board, 0);
+fn main() {
+    let mut args:Vec<String> = std::env::args().skip(1).collect();
+    let mut parameters = Params::default();
+    if let Some(index) = args.iter().position(|arg| arg == "--params"){
+        // the weights have to be in place before the first position is built
+        let path = args.get(index+1).cloned().unwrap_or_default();
+        args.drain(index..(index+2).min(args.len()));
+        match Params::load(&path){
+            Ok(loaded)=>parameters = loaded,
+            Err(error)=>exit_on_error(Err(error)),
+        }
+        exit_on_error(eval::set_params(parameters.eval.clone()));
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--nnue"){
+        // evaluate with a network instead of evaluate_position
+        let path = args.get(index+1).cloned().unwrap_or_default();
+        args.drain(index..(index+2).min(args.len()));
+        exit_on_error(nnue::Network::load(&path).and_then(nnue::set_network));
+    }
+    exit_on_error(syzygy::set_tablebase(&parameters.tablebase).map(|_| ()));
+    let command = match args.first().map(String::as_str){
+        Some(command) if !command.starts_with("--") || command == "--help"=>command.to_string(),
+        _=>"play".to_string(),
+    };
+    let rest = if args.first() == Some(&command){&args[1..]}else{&args[..]};
+    match command.as_str(){
+        "trace"=>return exit_on_error(trace::run(rest)),
+        "tune"=>return exit_on_error(tune::run(rest, &parameters)),
+        "params"=>return exit_on_error(params::run(rest, &parameters)),
+        "nnue"=>return exit_on_error(nnue::run(rest)),
+        "book"=>return exit_on_error(book::run(rest, &parameters.book)),
+        "syzygy"=>return exit_on_error(syzygy::run(rest)),
+        "help" | "--help" | "-h"=>return println!("{}", cli::USAGE),
+        "play" | "uci" | "xboard" | "perft" | "bench" | "analyze" | "selfplay"=>{},
+        _=>return exit_on_error(Err(format!("unknown command '{}', see chessbot help", command))),
+    }
+    let options = match cli::parse(&command, rest, &mut parameters){
+        Ok(options)=>options,
+        Err(error)=>return exit_on_error(Err(error)),
+    };
+    exit_on_error(match command.as_str(){
+        "uci"=>uci::run(&parameters),
+        "xboard"=>xboard::run(&parameters),
+        "perft"=>cli::run_perft(&options),
+        "bench"=>cli::run_bench(&options, &parameters),
+        "analyze"=>cli::run_analyze(&options, &parameters),
+        "selfplay"=>cli::run_selfplay(&options, &parameters),
+        _=>play(&options, &parameters),
+    });
+}
 
+fn play(options:&cli::Options, parameters:&Params)->Result<(), String>{
+    // a game in the terminal, the engine takes the side the human does not play
+    let params = parameters.search;
+    // the colour and a weakened engines moves come from the same seeded generator
+    let mut random = random::Random::new(params.seed);
+    let human = match options.color{
+        cli::Color::White=>true,
+        cli::Color::Black=>false,
+        cli::Color::Random=>random.below(2) == 0,
+    };
+    let (mut board, mut white) = options.position();
+    println!("let the chess begin, you play {}", if human{"white"}else{"black"});
+
+    let limits = options.limits(&params);
+    let tt = TranspositionTable::new(params.hash_mb);
+    let mut book = OpeningBook::new(&parameters.book)?;
+
+    let mut predicted:Option<Board> = None;     // the reply expected after the engines last move
+    let mut pondered:Option<SearchResult> = None;
+    let mut previous:Option<Board> = None;      // the position before the last move, for the book
+    let mut ply = options.start_ply(white);     // moves played so far
+    loop{
+        display_board_windows(&board, 0, human);
+        if game_over(&board, white){
+            break;
+        }
 
-        board = find_best_move(board, MOVE_SEARCH_DEPTH);
+        let before = board;
+        if white == human{
+            // with ponder on, the position after the expected reply is searched
+            // while waiting for the human, and that search is kept on a hit
+            let stop = AtomicBool::new(false);
+            pondered = std::thread::scope(|scope|{
+                let (limits, params, tt, stop) = (&limits, &params, &tt, &stop);
+                let ponder = predicted.map(|predicted| scope.spawn(move ||
+                    find_best_move(predicted, !white, limits, params, tt, stop, None)));
+                board = collect_move(board, white);
+                let hit = predicted.is_some_and(|predicted|
+                    notation::Move::between(&before, &predicted, white) == notation::Move::between(&before, &board, white));
+                if !hit{
+                    stop.store(true, Ordering::Relaxed);
+                }
+                let result = ponder.map(|ponder| ponder.join().unwrap());
+                result.filter(|_| hit)
+            });
+            if params.multi_pv > 1{
+                // the moves the engine would have considered instead
+                let full_strength = SearchParams{skill_level:skill::MAX_LEVEL, limit_strength:false, ..params};
+                let analysis = find_best_move(before, white, &limits, &full_strength, &tt, &AtomicBool::new(false), None);
+                println!("you played {}, the best moves were", notation::san(&before, &board, white));
+                println!("{}", analysis.lines_text(&before, white));
+            }
+        }else if let Some(book_move) = book.choose(&board, white, ply, previous.as_ref()){
+            println!("book move {}", notation::san(&board, &book_move, white));
+            predicted = None;
+            board = book_move;
+        }else{
+            let mut result = match pondered.take(){
+                Some(result)=>result,
+                None=>find_best_move(board, white, &limits, &params, &tt, &AtomicBool::new(false), None),
+            };
+            skill::choose(&params, &mut result, &mut random);
+            println!("{}", result.info_line(&board, white));
+            if params.multi_pv > 1{
+                println!("{}", result.lines_text(&board, white));
+            }
+            predicted = if params.ponder && result.pv.len() > 1{Some(result.pv[1])}else{None};
+            board = result.board;
+        }
+        previous = Some(before);
+        white = !white;
+        ply += 1;
+    }
+    Ok(())
+}
 
-        //modify move to board
-        //calculate best response
-        //make response
+fn game_over(board:&Board, white:bool)->bool{
+    // the game ends when the side to move has no legal moves left
+    if !legal_moves(board, white).is_empty(){
+        return false;
+    }
+    if board.in_check(white){
+        println!("checkmate, {} wins", if white{"black"}else{"white"});
+    }else{
+        println!("stalemate");
     }
+    true
 }
 
 #[allow(dead_code)]
@@ -811,7 +979,7 @@ fn print_mask(mask:u64, name:&str){
 }
 
 
-fn display_board_windows(board:&Board, moves:u64){
+fn display_board_windows(board:&Board, moves:u64, white:bool){
     let mut board_list:Vec<ColoredString> = Vec::with_capacity(64);
     for i in 0..64{
         let mut square:ColoredString;   
@@ -858,18 +1026,18 @@ fn display_board_windows(board:&Board, moves:u64){
         }
         board_list.push(square);
     }
-    let range = 0..8;
-    for i in range.into_iter().rev(){
-        println!("{}{}{}{}{}{}{}{}",
-            board_list[8*i+7], board_list[8*i+6],
-            board_list[8*i+5], board_list[8*i+4],
-            board_list[8*i+3], board_list[8*i+2],
-            board_list[8*i+1], board_list[8*i  ],
-        )
+    // seen from white rank 8 is on top and the a file on the left, from black the other way round
+    let ranks:Vec<usize> = if white{(0..8).rev().collect()}else{(0..8).collect()};
+    for rank in ranks{
+        let row:String = (0..8).map(|file| if white{7-file}else{file})
+            .map(|index| board_list[8*rank+index].to_string()).collect();
+        println!("{} {}", rank+1, row);
     }
+    println!("  {}", if white{"a b c d e f g h"}else{"h g f e d c b a"});
     println!(" ");
 }
 
+#[allow(dead_code)]
 fn display_board(board:&Board, moves:u64){
     let mut board_list:Vec<ColoredString> = Vec::with_capacity(64);
     for i in 0..64{
@@ -947,124 +1115,140 @@ fn display_board(board:&Board, moves:u64){
     println!(" ");
 }
 
-fn collect_white_move(mut board:Board)->Board{
-    let mut row = 9;
-    let mut col = 9;
-    while board.whites&(1<<(8*(col-1)+8-row))==0{
-        let mut input:String = "".to_owned();
-        println!("Select piece");
-        std::io::stdin().read_line(&mut input).unwrap();
-        println!("{}", input);
-        if input.chars().count() != 4{
-            println!("invalid square");
-            continue;
+fn read_square(prompt:&str)->u8{
+    // a square like e2 typed by the human, asked again until it is one
+    loop{
+        println!("{}", prompt);
+        let mut input = String::new();
+        if std::io::stdin().read_line(&mut input).unwrap_or(0) == 0{
+            std::process::exit(0);
         }
-        let mut iter = input.chars();
-        row = iter.next().unwrap() as u32 - 96;
-        col = iter.next().unwrap() as u32 - 48;
+        let name = input.trim().as_bytes();
+        if name.len() == 2 && (b'a'..=b'h').contains(&name[0]) && (b'1'..=b'8').contains(&name[1]){
+            return 8*(name[1]-b'1') + 7-(name[0]-b'a');
+        }
+        println!("invalid square");
     }
+}
 
-    println!("{}, {}", row, col);
-    let piece_mask = 1<<(8*(col-1)+8-row);
-    let move_squares:u64 = possible_white_moves(&board, piece_mask);
-
-    println!("moved squares{}", move_squares);
-    display_board_windows(&board, move_squares);
-
-    row = 9;
-    col = 9;
-    let mut moved_piece_mask = 0;
-    while moved_piece_mask&move_squares==0{
-        let mut input:String = "".to_owned();
-        println!("Select square");
-        std::io::stdin().read_line(&mut input).unwrap();
-        println!("{}", input);
-        if input.chars().count() != 4{
-            println!("invalid square");
+fn collect_move(board:Board, white:bool)->Board{
+    // the human picks one of their pieces and then one of the squares it can go to,
+    // the board is shown from their side with those squares marked
+    let colour = if white{"white"}else{"black"};
+    let own = if white{board.whites}else{board.blacks};
+    loop{
+        let from = read_square(&format!("Select a {} piece", colour));
+        if own & 1<<from == 0{
+            println!("no {} piece there", colour);
             continue;
         }
-        let mut iter = input.chars();
-        row = iter.next().unwrap() as u32 - 96;
-        col = iter.next().unwrap() as u32 - 48;
-        moved_piece_mask = 1<<(8*(col-1)+8-row);   
-    }
-    println!("{}, {}", row, col);
-    if moved_piece_mask&board.blacks != 0{
-        board.take(moved_piece_mask);
+        let moves:Vec<Board> = legal_moves(&board, white).into_iter()
+            .filter(|new_board| notation::Move::between(&board, new_board, white).from == from)
+            .collect();
+        if moves.is_empty(){
+            println!("that piece cannot move");
+            continue;
+        }
+        let move_squares = moves.iter().fold(0u64, |squares, new_board| squares | 1<<notation::Move::between(&board, new_board, white).to);
+        display_board_windows(&board, move_squares, white);
+        let to = read_square("Select square");
+        if let Some(new_board) = moves.iter().find(|new_board| notation::Move::between(&board, new_board, white).to == to){
+            return *new_board;
+        }
+        println!("the piece cannot go there, select a piece again");
     }
-    move_white_piece(&mut board, piece_mask, moved_piece_mask);
-    //print_mask(board.pawns, "pawns");
-    // mark and display the possible moves from move_squares
-    // collect second input, move piece and return the board
-    return board;
 }
-
-fn possible_white_moves(board:&Board, piece_mask:u64)->u64{
-
-    // figure out what piece it is
-    // use engine to find possible next positions
-    // match difference between posissions and the current
-    // make those into bitmaps and return them
-
-    let mut moves = 0u64;
-    if piece_mask & board.pawns != 0{
-        let mut pawn_bitmap = !(piece_mask|piece_mask<<8|piece_mask<<16);
-        while let Some(new_board) = find_new_white_pawn_move(board, &mut pawn_bitmap){
-            let new_move = (board.pawns&board.whites)^(new_board.pawns&new_board.whites)^piece_mask;
-            moves |= new_move;
-        }
-    }else if piece_mask & board.knights != 0{
-        let mut knight_bitmap = board.knights^piece_mask;
-        while let Some(new_board) = find_new_knight_move(board, &mut knight_bitmap, board.whites){
-            let new_move = (board.knights&board.whites)^(new_board.knights&new_board.whites)^piece_mask;
-            moves |= new_move;
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::notation::{legal_moves, parse_fen};
+
+    const FILES_A_B:u64 = 0xC0C0C0C0C0C0C0C0;
+
+    #[test]
+    fn knights_on_the_h_file_stay_off_the_a_and_b_files(){
+        let (board, _) = parse_fen("3k4/8/8/8/8/8/8/3K4 w - - 0 1").unwrap();
+        for rank in 0..8{
+            // h1 is square 0, so the h file is every eighth square
+            let mut board = board;
+            board.knights |= 1<<(8*rank);
+            board.whites |= 1<<(8*rank);
+            let mut knight_bitmap = 0;
+            while let Some(new_board) = find_new_knight_move(&board, &mut knight_bitmap, board.whites){
+                assert_eq!(new_board.knights & FILES_A_B, 0, "knight on h{} wrapped to the a or b file", rank+1);
+            }
         }
-    } else if piece_mask & board.bishops != 0{
-        println!("recognized bishop");
-        let mut bishop_bitmap = board.bishops^piece_mask;
-        //print_mask(bishop_bitmap, "bishop bitmap");
-        while let Some(new_board) = find_new_bishop_move(board, &mut bishop_bitmap, board.whites){
-            //println!("found move");
-            let new_move = (board.bishops&board.whites)^(new_board.bishops&new_board.whites)^piece_mask;
-            moves |= new_move;
+    }
+    #[test]
+    fn pawns_reaching_the_last_rank_become_queens(){
+        let (board, white) = parse_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let moves = legal_moves(&board, white);
+        // a8 is square 63
+        assert!(moves.iter().any(|new_board| new_board.queens == 1<<63 && new_board.pawns == 0));
+        assert!(moves.iter().all(|new_board| new_board.pawns & PROMOTION_ROWS == 0));
+
+        let (board, white) = parse_fen("4k3/8/8/8/8/8/7p/4K1N1 b - - 0 1").unwrap();
+        let moves = legal_moves(&board, white);
+        // h1 is square 0, g1 square 1
+        assert!(moves.iter().any(|new_board| new_board.queens == 1<<0 && new_board.blacks & 1<<0 != 0));
+        assert!(moves.iter().any(|new_board| new_board.queens == 1<<1 && new_board.knights == 0));
+        assert!(moves.iter().all(|new_board| new_board.pawns & PROMOTION_ROWS == 0));
+    }
+
+    #[test]
+    fn pawns_do_not_push_onto_or_over_pieces(){
+        // a knight in front of every pawn, so none can push one square or jump two
+        let (board, _) = parse_fen("4k3/p6p/n6n/8/8/N6N/P6P/4K3 w - - 0 1").unwrap();
+        for white in [true, false]{
+            for new_board in legal_moves(&board, white){
+                assert_eq!(new_board.pawns, board.pawns, "a blocked pawn moved");
+            }
         }
-    } else if piece_mask & board.rooks != 0{
-        let mut rook_bitmap = board.rooks^piece_mask;
-        while let Some(new_board) = find_new_rook_move(board, &mut rook_bitmap, board.whites){
-            let new_move = (board.rooks&board.whites)^(new_board.rooks&new_board.whites)^piece_mask;
-            moves |= new_move;
+    }
+
+    #[test]
+    fn sliders_stop_at_pieces_of_their_own_kind(){
+        // a3 is done before a1 is looked at, a1 must not go through it
+        let (board, _) = parse_fen("4k3/8/8/8/8/R7/8/R3K3 w - - 0 1").unwrap();
+        let mut rook_bitmap = 0;
+        let mut moves = 0;
+        while find_new_rook_move(&board, &mut rook_bitmap, board.whites).is_some(){
+            moves += 1;
         }
-    } else if piece_mask & board.queens != 0{
+        assert_eq!(moves, 13 + 4);
+    }
+
+    #[test]
+    fn every_queen_moves_once_to_each_square(){
+        // a1 is done before h1 is looked at, which must not forget the squares h1 went to
+        let (board, _) = parse_fen("4k3/8/8/8/8/8/8/Q3K2Q w - - 0 1").unwrap();
         let mut queen_bitmap = 0;
-        while let Some(new_board) = find_new_queen_move(board, &mut queen_bitmap, board.whites){
-            let new_move = (board.queens&board.whites)^(new_board.queens&new_board.whites)^piece_mask;
-            moves |= new_move;
+        let mut moves = 0;
+        // bounded, the generator used to go round forever here
+        while find_new_queen_move(&board, &mut queen_bitmap, board.whites).is_some() && moves < 100{
+            moves += 1;
         }
-    } else if piece_mask & board.kings != 0{
-        let mut king_bitmap = 0;
-        while let Some(new_board) = find_new_king_move(board, &mut king_bitmap, board.whites){
-            let new_move = (board.kings&board.whites)^(new_board.kings&new_board.whites)^piece_mask;
-            moves |= new_move;
-        }
-    } 
+        assert_eq!(moves, 17 + 16);
+    }
 
-    return moves;
-}
+    fn check_tables(board:&Board, white:bool, depth:u32){
+        for (child, _) in search::generate_moves(board, white){
+            assert_eq!((child.mg, child.eg), eval::material_and_tables(&child, eval::params()));
+            if depth > 1{
+                check_tables(&child, !white, depth-1);
+            }
+        }
+    }
 
-#[inline]
-fn move_white_piece(board:&mut Board, piece_mask:u64, moved_piece_mask:u64){
-    board.whites ^= piece_mask|moved_piece_mask;
-    if piece_mask & board.pawns != 0{
-        board.pawns ^= piece_mask|moved_piece_mask;
-    }else if piece_mask & board.knights != 0{
-        board.knights ^= piece_mask|moved_piece_mask;
-    }else if piece_mask & board.bishops != 0{
-        board.bishops ^= piece_mask|moved_piece_mask;
-    }else if piece_mask & board.rooks != 0{
-        board.rooks ^= piece_mask|moved_piece_mask;
-    }else if piece_mask & board.queens != 0{
-        board.queens ^= piece_mask|moved_piece_mask;
-    }else{
-        board.kings ^= piece_mask|moved_piece_mask;
+    #[test]
+    fn incremental_tables_match_a_full_count(){
+        // quiet moves, captures, promotions with and without a capture
+        for fen in [notation::START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1"]{
+            let (board, white) = parse_fen(fen).unwrap();
+            assert_eq!((board.mg, board.eg), eval::material_and_tables(&board, eval::params()));
+            check_tables(&board, white, 3);
+        }
     }
-}
\ No newline at end of file
+}
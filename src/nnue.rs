@@ -0,0 +1,325 @@
+// efficiently updatable neural network evaluation, used instead of
+// evaluate_position when a network file is loaded with --nnue.
+//
+// architecture: 768 inputs -> hidden layer of size H, computed once from the
+// side to move's view and once from the opponent's with the same weights ->
+// clipped relu -> one output.
+//
+// an input is one (colour, piece, square) triple seen from a perspective:
+//     index = (own piece ? 0 : 384) + piece*64 + square
+// with pieces ordered pawn, knight, bishop, rook, queen, king and squares
+// numbered a1=0, b1=1 .. h8=63 for white. black sees the board mirrored
+// vertically, so its a8 is square 0.
+//
+// file format, every number little endian:
+//     bytes 0..4    magic "CBNN"
+//     u32           version, 1
+//     u32           H, the hidden layer size
+//     i16 x 768*H   input weights, all H weights of input 0 first
+//     i16 x H       hidden biases
+//     i16 x 2*H     output weights, the side to move's half first
+//     i32           output bias
+// the accumulator is quantised by QA, the output weights by QB, and
+//     eval = (sum of clamp(hidden, 0, QA) * output weight + output bias) * SCALE / (QA*QB)
+// in centipawns for the side to move.
+
+use std::sync::OnceLock;
+
+use crate::eval::{self, piece_maps};
+use crate::notation::{parse_fen, START_FEN};
+use crate::score::Score;
+use crate::Board;
+
+const MAGIC:&[u8; 4] = b"CBNN";
+const VERSION:u32 = 1;
+const INPUTS:usize = 768;
+pub const QA:i32 = 255;
+pub const QB:i32 = 64;
+pub const SCALE:i32 = 400;
+
+pub struct Network{
+    hidden:usize,
+    input_weights:Vec<i16>,
+    hidden_bias:Vec<i16>,
+    output_weights:Vec<i16>,
+    output_bias:i32,
+}
+
+// hidden layer values from whites and from blacks point of view
+#[derive(Clone, PartialEq, Debug)]
+pub struct Accumulator{
+    white:Vec<i16>,
+    black:Vec<i16>,
+}
+
+fn feature(piece:usize, piece_white:bool, square:u32, perspective_white:bool)->usize{
+    // bitmap squares count from h1, network squares from a1
+    let rank = square/8;
+    let file = 7 - square%8;
+    let oriented = if perspective_white{rank*8 + file}else{(7-rank)*8 + file};
+    let colour = if piece_white == perspective_white{0}else{384};
+    colour + piece*64 + oriented as usize
+}
+
+impl Network{
+    pub fn from_bytes(bytes:&[u8])->Result<Network, String>{
+        let mut reader = bytes;
+        let mut take = |length:usize|->Result<&[u8], String>{
+            if reader.len() < length{
+                return Err("network file is truncated".to_string());
+            }
+            let (head, rest) = reader.split_at(length);
+            reader = rest;
+            Ok(head)
+        };
+        if take(4)? != MAGIC{
+            return Err("not a network file, the magic bytes are wrong".to_string());
+        }
+        let version = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        if version != VERSION{
+            return Err(format!("network file version {} is not supported", version));
+        }
+        let hidden = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        if hidden == 0 || hidden > 4096{
+            return Err(format!("hidden layer size {} is out of range", hidden));
+        }
+        let mut weights = |count:usize|->Result<Vec<i16>, String>{
+            Ok(take(2*count)?.chunks_exact(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])).collect())
+        };
+        let input_weights = weights(INPUTS*hidden)?;
+        let hidden_bias = weights(hidden)?;
+        let output_weights = weights(2*hidden)?;
+        let output_bias = i32::from_le_bytes(take(4)?.try_into().unwrap());
+        if !reader.is_empty(){
+            return Err(format!("{} unexpected bytes after the network", reader.len()));
+        }
+        Ok(Network{hidden, input_weights, hidden_bias, output_weights, output_bias})
+    }
+
+    pub fn to_bytes(&self)->Vec<u8>{
+        let mut bytes = Vec::with_capacity(16 + 2*(INPUTS+3)*self.hidden);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.hidden as u32).to_le_bytes());
+        for weight in self.input_weights.iter().chain(&self.hidden_bias).chain(&self.output_weights){
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.output_bias.to_le_bytes());
+        bytes
+    }
+
+    pub fn load(path:&str)->Result<Network, String>{
+        let bytes = std::fs::read(path).map_err(|error| format!("cannot read {}: {}", path, error))?;
+        Network::from_bytes(&bytes).map_err(|error| format!("{}: {}", path, error))
+    }
+
+    fn add_feature(&self, values:&mut [i16], index:usize, sign:i16){
+        let weights = &self.input_weights[index*self.hidden..(index+1)*self.hidden];
+        for (value, weight) in values.iter_mut().zip(weights){
+            *value = value.wrapping_add(sign.wrapping_mul(*weight));
+        }
+    }
+
+    fn add_square(&self, accumulator:&mut Accumulator, board:&Board, square:u32, sign:i16){
+        // every piece bitmap is checked, the same way the tables count pieces
+        let mask = 1u64<<square;
+        for (piece, piece_map) in piece_maps(board).iter().enumerate(){
+            for (piece_white, color_map) in [(true, board.whites), (false, board.blacks)]{
+                if piece_map & color_map & mask != 0{
+                    self.add_feature(&mut accumulator.white, feature(piece, piece_white, square, true), sign);
+                    self.add_feature(&mut accumulator.black, feature(piece, piece_white, square, false), sign);
+                }
+            }
+        }
+    }
+
+    pub fn refresh(&self, board:&Board)->Accumulator{
+        // the accumulator computed from scratch
+        let mut accumulator = Accumulator{white:self.hidden_bias.clone(), black:self.hidden_bias.clone()};
+        let mut occupied = board.whites|board.blacks;
+        while occupied != 0{
+            self.add_square(&mut accumulator, board, occupied.trailing_zeros(), 1);
+            occupied &= occupied-1;
+        }
+        accumulator
+    }
+
+    pub fn update(&self, parent_accumulator:&Accumulator, child:&mut Accumulator, parent:&Board, board:&Board){
+        // the child accumulator from its parent, only the squares the move
+        // touched are taken out and put back in
+        child.white.copy_from_slice(&parent_accumulator.white);
+        child.black.copy_from_slice(&parent_accumulator.black);
+        let mut changed = (parent.whites^board.whites) | (parent.blacks^board.blacks);
+        for (before, after) in piece_maps(parent).iter().zip(piece_maps(board).iter()){
+            changed |= before^after;
+        }
+        while changed != 0{
+            let square = changed.trailing_zeros();
+            self.add_square(child, parent, square, -1);
+            self.add_square(child, board, square, 1);
+            changed &= changed-1;
+        }
+    }
+
+    pub fn empty_accumulator(&self)->Accumulator{
+        Accumulator{white:vec![0; self.hidden], black:vec![0; self.hidden]}
+    }
+
+    pub fn evaluate(&self, accumulator:&Accumulator, white:bool)->Score{
+        let (own, other) = if white{(&accumulator.white, &accumulator.black)}else{(&accumulator.black, &accumulator.white)};
+        let mut output = self.output_bias as i64;
+        for (value, weight) in own.iter().chain(other.iter()).zip(&self.output_weights){
+            output += (*value as i32).clamp(0, QA) as i64 * *weight as i64;
+        }
+        let centipawns = output * SCALE as i64 / (QA*QB) as i64;
+        Score::from_eval(centipawns.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+    }
+
+    pub fn from_tables(params:&eval::EvalParams)->Network{
+        // a two unit network reproducing the middlegame material and tables,
+        // mostly useful to check the file format and the accumulator updates.
+        // unit 0 sums the own pieces and unit 1 the opponents, both in steps
+        // of STEP centipawns around a bias of half the clamp range
+        const STEP:i32 = 32;
+        let hidden = 2;
+        let mut input_weights = vec![0i16; INPUTS*hidden];
+        for piece in 0..6{
+            for square in 0..64{
+                // network square 0 is a1 and the tables start at a8. the
+                // opponent's pieces read the table from their own side
+                let own_square = (7 - square/8)*8 + square%8;
+                let own = (params.mg_values[piece] + params.mg_tables[piece][own_square]) / STEP;
+                let opponent = (params.mg_values[piece] + params.mg_tables[piece][square]) / STEP;
+                input_weights[(piece*64 + square)*hidden] = own as i16;
+                input_weights[(384 + piece*64 + square)*hidden + 1] = opponent as i16;
+            }
+        }
+        // output = w*(own - opponent) and eval = output*SCALE/(QA*QB) = STEP*(own - opponent)
+        let weight = (STEP*QA*QB/SCALE) as i16;
+        Network{
+            hidden,
+            input_weights,
+            hidden_bias:vec![(QA/2) as i16; hidden],
+            output_weights:vec![weight, -weight, 0, 0],
+            output_bias:0,
+        }
+    }
+}
+
+static NETWORK:OnceLock<Network> = OnceLock::new();
+
+pub fn network()->Option<&'static Network>{
+    // the loaded network, evaluate_position is used when there is none
+    NETWORK.get()
+}
+
+pub fn set_network(network:Network)->Result<(), String>{
+    NETWORK.set(network).map_err(|_| "a network is already loaded".to_string())
+}
+
+pub fn run(args:&[String])->Result<(), String>{
+    // nnue export <file>: writes the table network described at from_tables
+    // nnue eval [fen]: evaluates a position with the network loaded by --nnue
+    match args{
+        [command, fen @ ..] if command == "eval"=>{
+            let network = network().ok_or("nnue eval needs a network, load one with --nnue <file>")?;
+            let fen = fen.join(" ");
+            let (board, white) = parse_fen(if fen.is_empty(){START_FEN}else{&fen})?;
+            let score = network.evaluate(&network.refresh(&board), white);
+            println!("score {} for {}", score, if white{"white"}else{"black"});
+            Ok(())
+        }
+        [command, path] if command == "export"=>{
+            let network = Network::from_tables(eval::params());
+            std::fs::write(path, network.to_bytes()).map_err(|error| format!("cannot write {}: {}", path, error))?;
+            println!("wrote {}", path);
+            Ok(())
+        }
+        _=>Err("usage: nnue export <file> | nnue eval [fen]".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::search::generate_moves;
+
+    fn patterned(hidden:usize)->Network{
+        // small weights that differ for every input, so a misplaced feature shows
+        let pattern = |index:usize| (index*7919 % 61) as i16 - 30;
+        Network{
+            hidden,
+            input_weights:(0..INPUTS*hidden).map(pattern).collect(),
+            hidden_bias:(0..hidden).map(|index| pattern(index+1) + 100).collect(),
+            output_weights:(0..2*hidden).map(|index| pattern(index+5)).collect(),
+            output_bias:-1234,
+        }
+    }
+
+    fn check_updates(network:&Network, board:&Board, accumulator:&Accumulator, white:bool, depth:u32){
+        for (child, _) in generate_moves(board, white){
+            let mut child_accumulator = network.empty_accumulator();
+            network.update(accumulator, &mut child_accumulator, board, &child);
+            assert_eq!(child_accumulator, network.refresh(&child));
+            if depth > 1{
+                check_updates(network, &child, &child_accumulator, !white, depth-1);
+            }
+        }
+    }
+
+    #[test]
+    fn features_count_from_each_sides_a_file_and_back_rank(){
+        // h1 is bitmap square 0, a1 is 7 and a8 is 63
+        assert_eq!(feature(0, true, 7, true), 0);
+        assert_eq!(feature(0, false, 63, false), 0);
+        assert_eq!(feature(5, true, 0, true), 5*64 + 7);
+        assert_eq!(feature(5, true, 0, false), 384 + 5*64 + 63);
+    }
+
+    #[test]
+    fn networks_read_back_what_they_write(){
+        let network = patterned(8);
+        let bytes = network.to_bytes();
+        assert_eq!(bytes.len(), 16 + 2*(INPUTS+3)*8);
+        let read = Network::from_bytes(&bytes).unwrap();
+        assert_eq!(read.to_bytes(), bytes);
+
+        assert!(Network::from_bytes(&bytes[..bytes.len()-1]).is_err());
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(Network::from_bytes(&longer).is_err());
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert!(Network::from_bytes(&magic).is_err());
+        let mut version = bytes.clone();
+        version[4] = 2;
+        assert!(Network::from_bytes(&version).is_err());
+        let mut hidden = bytes;
+        hidden[8..12].copy_from_slice(&0u32.to_le_bytes());
+        assert!(Network::from_bytes(&hidden).is_err());
+    }
+
+    #[test]
+    fn updated_accumulators_match_a_refresh(){
+        for network in [patterned(16), Network::from_tables(eval::params())]{
+            for fen in [START_FEN,
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1",
+                "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1"]{
+                let (board, white) = parse_fen(fen).unwrap();
+                check_updates(&network, &board, &network.refresh(&board), white, 3);
+            }
+        }
+    }
+
+    #[test]
+    fn the_table_network_sees_material(){
+        let network = Network::from_tables(eval::params());
+        let (start, _) = parse_fen(START_FEN).unwrap();
+        let accumulator = network.refresh(&start);
+        assert_eq!(network.evaluate(&accumulator, true), network.evaluate(&accumulator, false));
+        let (queen_up, _) = parse_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        let accumulator = network.refresh(&queen_up);
+        assert!(network.evaluate(&accumulator, true) > Score::new(500));
+        assert!(network.evaluate(&accumulator, false) < Score::new(-500));
+    }
+}
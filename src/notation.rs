@@ -0,0 +1,360 @@
+// converting between positions and human readable moves
+
+use crate::{eval, Board};
+use crate::search::generate_moves;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Move{
+    pub from:u8,    // square indexes, a1 is 7 and h8 is 56 like the bitmaps
+    pub to:u8,
+}
+
+impl Move{
+    pub fn between(board:&Board, new_board:&Board, white:bool)->Move{
+        // a move is the one own piece that left a square and the one that arrived
+        let (before, after) = if white{(board.whites, new_board.whites)}else{(board.blacks, new_board.blacks)};
+        let from = before & !after;
+        let to = after & !before;
+        Move{from:from.trailing_zeros() as u8, to:to.trailing_zeros() as u8}
+    }
+}
+
+pub fn square_name(square:u8)->String{
+    let file = (b'h' - square%8) as char;
+    let rank = (b'1' + square/8) as char;
+    format!("{}{}", file, rank)
+}
+
+fn piece_letter(board:&Board, square:u64)->&'static str{
+    if board.pawns & square != 0{
+        ""
+    }else if board.knights & square != 0{
+        "N"
+    }else if board.bishops & square != 0{
+        "B"
+    }else if board.rooks & square != 0{
+        "R"
+    }else if board.queens & square != 0{
+        "Q"
+    }else{
+        "K"
+    }
+}
+
+pub fn legal_moves(board:&Board, white:bool)->Vec<Board>{
+    // pseudo legal moves that dont leave the own king attacked
+    generate_moves(board, white).into_iter()
+        .map(|(new_board, _)| new_board)
+        .filter(|new_board| !new_board.in_check(white))
+        .collect()
+}
+
+pub fn san(board:&Board, new_board:&Board, white:bool)->String{
+    let played = Move::between(board, new_board, white);
+    let from_mask = 1u64<<played.from;
+    let letter = piece_letter(board, from_mask);
+    let enemies = if white{board.blacks}else{board.whites};
+    let capture = (enemies & !(if white{new_board.blacks}else{new_board.whites})) != 0;
+
+    let mut san = String::from(letter);
+    if letter.is_empty(){
+        if capture{
+            san.push_str(&square_name(played.from)[..1]);
+        }
+    }else{
+        // disambiguate between pieces of the same type reaching the same square
+        let rivals:Vec<Move> = legal_moves(board, white).iter()
+            .map(|other| Move::between(board, other, white))
+            .filter(|other| other.to == played.to && other.from != played.from
+                && piece_letter(board, 1u64<<other.from) == letter)
+            .collect();
+        if !rivals.is_empty(){
+            let name = square_name(played.from);
+            if rivals.iter().all(|other| other.from%8 != played.from%8){
+                san.push_str(&name[..1]);
+            }else if rivals.iter().all(|other| other.from/8 != played.from/8){
+                san.push_str(&name[1..]);
+            }else{
+                san.push_str(&name);
+            }
+        }
+    }
+    if capture{
+        san.push('x');
+    }
+    san.push_str(&square_name(played.to));
+    if letter.is_empty() && new_board.queens & 1u64<<played.to != 0{
+        san.push_str("=Q");
+    }
+
+    if new_board.in_check(!white){
+        if legal_moves(new_board, !white).is_empty(){
+            san.push('#');
+        }else{
+            san.push('+');
+        }
+    }
+    san
+}
+
+pub fn pv_to_san(board:&Board, pv:&[Board], white:bool)->String{
+    // the pv is the chain of positions after each move, starting from board
+    let mut line:Vec<String> = Vec::with_capacity(pv.len());
+    let mut previous = board;
+    let mut side = white;
+    for position in pv{
+        line.push(san(previous, position, side));
+        previous = position;
+        side = !side;
+    }
+    line.join(" ")
+}
+
+pub fn uci_move(board:&Board, new_board:&Board, white:bool)->String{
+    // long algebraic notation, e2e4 or e7e8q
+    let played = Move::between(board, new_board, white);
+    let mut text = format!("{}{}", square_name(played.from), square_name(played.to));
+    if board.pawns & 1u64<<played.from != 0 && new_board.pawns & 1u64<<played.to == 0{
+        text.push('q');
+    }
+    text
+}
+
+pub fn parse_uci_move(board:&Board, white:bool, text:&str)->Result<Board, String>{
+    // the generators only promote to a queen, so any promotion letter is played as one
+    let wanted = match (text.len(), text.get(4..)){
+        (4, _)=>text.to_string(),
+        (5, Some(promotion)) if "qrbn".contains(promotion)=>format!("{}q", &text[..4]),
+        _=>return Err(format!("cannot read move '{}'", text)),
+    };
+    legal_moves(board, white).into_iter()
+        .find(|new_board| uci_move(board, new_board, white) == wanted)
+        .ok_or(format!("illegal move '{}'", text))
+}
+
+pub fn parse_san(board:&Board, white:bool, text:&str)->Result<Board, String>{
+    // standard algebraic notation as found in pgn files, check marks and
+    // annotations are ignored. castling and under promotion are not played
+    // by the generators, so they are reported as illegal
+    let core = text.trim_end_matches(['+', '#', '!', '?']);
+    if core.starts_with("O-O") || core.starts_with("0-0"){
+        return Err(format!("cannot play castling '{}'", text));
+    }
+    let (core, promotion) = match core.split_once('='){
+        Some((core, "Q"))=>(core, true),
+        Some(_)=>return Err(format!("cannot play under promotion '{}'", text)),
+        None=>(core, false),
+    };
+    let letter = if core.starts_with(['N', 'B', 'R', 'Q', 'K']){&core[..1]}else{""};
+    let rest = core[letter.len()..].replace('x', "");
+    if rest.len() < 2 || !rest.is_ascii(){
+        return Err(format!("cannot read move '{}'", text));
+    }
+    let (from_hint, target) = rest.split_at(rest.len()-2);
+    let candidates:Vec<Board> = legal_moves(board, white).into_iter().filter(|new_board|{
+        let played = Move::between(board, new_board, white);
+        let from = square_name(played.from);
+        square_name(played.to) == target
+            && piece_letter(board, 1u64<<played.from) == letter
+            && from_hint.chars().all(|hint| from.contains(hint))
+            && (letter.is_empty() && (played.to/8).is_multiple_of(7)) == promotion
+    }).collect();
+    match candidates.as_slice(){
+        [new_board]=>Ok(*new_board),
+        []=>Err(format!("illegal move '{}'", text)),
+        _=>Err(format!("ambiguous move '{}'", text)),
+    }
+}
+
+pub const START_FEN:&str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+pub fn parse_fen(fen:&str)->Result<(Board, bool), String>{
+    // returns the position and whether white is to move.
+    // the en passant field is accepted but not used, the generators never
+    // carry an en passant square over to a child position
+    let fields:Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() < 2{
+        return Err(format!("fen needs at least placement and side to move: '{}'", fen));
+    }
+    let mut board = Board::new();
+    board.kings = 0;
+    board.queens = 0;
+    board.rooks = 0;
+    board.bishops = 0;
+    board.knights = 0;
+    board.pawns = 0;
+    board.whites = 0;
+    board.blacks = 0;
+    board.castelable_pieces = 0;
+
+    let ranks:Vec<&str> = fields[0].split('/').collect();
+    if ranks.len() != 8{
+        return Err(format!("fen placement needs 8 ranks: '{}'", fields[0]));
+    }
+    for (row, rank_text) in ranks.iter().enumerate(){
+        let rank = 7-row as u32;
+        let mut file = 0u32;
+        for symbol in rank_text.chars(){
+            if let Some(empty) = symbol.to_digit(10){
+                file += empty;
+                continue;
+            }
+            if file > 7{
+                return Err(format!("fen rank {} is too long", rank+1));
+            }
+            let square = 1u64<<(8*rank + 7-file);
+            match symbol.to_ascii_lowercase(){
+                'k'=>board.kings |= square,
+                'q'=>board.queens |= square,
+                'r'=>board.rooks |= square,
+                'b'=>board.bishops |= square,
+                'n'=>board.knights |= square,
+                'p'=>board.pawns |= square,
+                _=>return Err(format!("unknown piece '{}' in fen", symbol)),
+            }
+            if symbol.is_ascii_uppercase(){
+                board.whites |= square;
+            }else{
+                board.blacks |= square;
+            }
+            file += 1;
+        }
+        if file != 8{
+            return Err(format!("fen rank {} does not have 8 files", rank+1));
+        }
+    }
+    if (board.kings & board.whites).count_ones() != 1 || (board.kings & board.blacks).count_ones() != 1{
+        return Err("fen needs exactly one king per side".to_string());
+    }
+    if board.pawns & 0xFF000000000000FF != 0{
+        // the pawn structure terms look one rank past every pawn
+        return Err("fen has a pawn on the first or last rank".to_string());
+    }
+
+    let white = match fields[1]{
+        "w"=>true,
+        "b"=>false,
+        other=>return Err(format!("unknown side to move '{}'", other)),
+    };
+
+    // castling rights are stored as the rooks and kings that have not moved
+    for right in fields.get(2).unwrap_or(&"-").chars(){
+        board.castelable_pieces |= match right{
+            'K'=>0x09,
+            'Q'=>0x88,
+            'k'=>0x09<<56,
+            'q'=>0x88<<56,
+            '-'=>0,
+            _=>return Err(format!("unknown castling right '{}'", right)),
+        };
+    }
+    if let Some(halfmoves) = fields.get(4){
+        board.fifty_rule = halfmoves.parse().map_err(|_| format!("bad halfmove clock '{}'", halfmoves))?;
+    }
+    (board.mg, board.eg) = eval::material_and_tables(&board, eval::params());
+    Ok((board, white))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const POSITIONS:[&str; 4] = [
+        START_FEN,
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+    ];
+
+    #[test]
+    fn the_start_fen_is_the_start_position(){
+        let (board, white) = parse_fen(START_FEN).unwrap();
+        let start = Board::new();
+        assert!(white);
+        assert_eq!([board.kings, board.queens, board.rooks, board.bishops, board.knights, board.pawns, board.whites, board.blacks],
+            [start.kings, start.queens, start.rooks, start.bishops, start.knights, start.pawns, start.whites, start.blacks]);
+        assert_eq!(board.castelable_pieces, 0x89 | 0x89<<56);
+        assert_eq!((board.mg, board.eg), (start.mg, start.eg));
+    }
+
+    #[test]
+    fn broken_fens_are_rejected(){
+        for fen in [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w",
+            "rnbqkbnr/pppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR w",
+            "rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w",
+            "rnbqqbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w",
+            "rnbqkbnP/pppppppp/8/8/8/8/PPPPPPP1/RNBQKBNR w",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - many 1",
+        ]{
+            assert!(parse_fen(fen).is_err(), "accepted '{}'", fen);
+        }
+    }
+
+    #[test]
+    fn every_san_move_reads_back(){
+        for fen in POSITIONS{
+            let (board, white) = parse_fen(fen).unwrap();
+            for new_board in legal_moves(&board, white){
+                let text = san(&board, &new_board, white);
+                let read = parse_san(&board, white, &text).map(|read| Move::between(&board, &read, white));
+                assert_eq!(read, Ok(Move::between(&board, &new_board, white)), "{} in {}", text, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn san_disambiguates_checks_and_promotes(){
+        // three knights reach d2, b1 shares a file with b3 and a rank with f1
+        let (board, white) = parse_fen("4k3/8/8/8/8/1N6/8/1N2KN2 w - - 0 1").unwrap();
+        let moves:Vec<String> = legal_moves(&board, white).iter().map(|new_board| san(&board, new_board, white)).collect();
+        for text in ["Nb1d2", "N3d2", "Nfd2", "Nc5", "Ng3"]{
+            assert!(moves.contains(&text.to_string()), "{} not in {:?}", text, moves);
+        }
+
+        let (mut board, mut white) = parse_fen(START_FEN).unwrap();
+        let mut line = Vec::new();
+        for text in ["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6", "Qxf7#"]{
+            let new_board = parse_san(&board, white, text).unwrap();
+            line.push(new_board);
+            (board, white) = (new_board, !white);
+        }
+        let (start, _) = parse_fen(START_FEN).unwrap();
+        assert_eq!(pv_to_san(&start, &line, true), "e4 e5 Bc4 Nc6 Qh5 Nf6 Qxf7#");
+
+        let (board, white) = parse_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1").unwrap();
+        let new_board = parse_san(&board, white, "gxf1=Q+").unwrap();
+        assert_eq!(san(&board, &new_board, white), "gxf1=Q+");
+        assert!(parse_san(&board, white, "g1=N").is_err());
+        assert!(parse_san(&board, white, "O-O").is_err());
+    }
+
+    #[test]
+    fn every_uci_move_reads_back(){
+        for fen in POSITIONS{
+            let (board, white) = parse_fen(fen).unwrap();
+            for new_board in legal_moves(&board, white){
+                let text = uci_move(&board, &new_board, white);
+                let read = parse_uci_move(&board, white, &text).map(|read| Move::between(&board, &read, white));
+                assert_eq!(read, Ok(Move::between(&board, &new_board, white)), "{} in {}", text, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn uci_promotions_are_played_as_queens_and_bad_moves_rejected(){
+        let (board, white) = parse_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N w - - 0 1").unwrap();
+        // b8 is square 62, a8 63 and c8 61
+        for (text, square) in [("b7b8q", 62), ("b7b8n", 62), ("b7a8r", 63), ("b7c8b", 61)]{
+            let new_board = parse_uci_move(&board, white, text).unwrap();
+            assert_eq!(new_board.queens & new_board.whites, 1<<square, "{}", text);
+        }
+        for text in ["", "e2", "b7b8", "e2e5", "b7b8k", "b7b8qq", "e2eé", "éééé", "a1a1"]{
+            assert!(parse_uci_move(&board, white, text).is_err(), "accepted '{}'", text);
+        }
+    }
+}
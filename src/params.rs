@@ -0,0 +1,605 @@
+// parameter files, holding every evaluation weight and search parameter so a
+// run can be reproduced from its file. two formats are read and written:
+// a small subset of toml ([sections], key = value with integers, floats,
+// booleans, strings and arrays, # comments) and json with one object per section
+
+use crate::book::BookParams;
+use crate::eval::{EvalParams, DEFAULT_PARAMS};
+use crate::random::Seed;
+use crate::search::{SearchParams, MAX_PLY};
+use crate::skill;
+use crate::syzygy::TablebaseParams;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value{
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),   // json only
+    Null,
+}
+
+fn strip_comment(line:&str)->&str{
+    let mut in_string = false;
+    for (index, symbol) in line.char_indices(){
+        match symbol{
+            '"'=>in_string = !in_string,
+            '#' if !in_string=>return &line[..index],
+            _=>{}
+        }
+    }
+    line
+}
+
+fn bracket_depth(text:&str)->i32{
+    let mut depth = 0;
+    let mut in_string = false;
+    for symbol in text.chars(){
+        match symbol{
+            '"'=>in_string = !in_string,
+            '[' if !in_string=>depth += 1,
+            ']' if !in_string=>depth -= 1,
+            _=>{}
+        }
+    }
+    depth
+}
+
+struct ValueParser<'a>{
+    text:&'a [u8],
+    position:usize,
+}
+
+impl ValueParser<'_>{
+    fn skip_spaces(&mut self){
+        while self.position < self.text.len() && self.text[self.position].is_ascii_whitespace(){
+            self.position += 1;
+        }
+    }
+
+    fn value(&mut self)->Result<Value, String>{
+        self.skip_spaces();
+        match self.text.get(self.position){
+            Some(b'[')=>{
+                self.position += 1;
+                let mut values = Vec::new();
+                loop{
+                    self.skip_spaces();
+                    if self.text.get(self.position) == Some(&b']'){
+                        self.position += 1;
+                        return Ok(Value::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.skip_spaces();
+                    match self.text.get(self.position){
+                        Some(b',')=>self.position += 1,
+                        Some(b']')=>{}
+                        _=>return Err("expected , or ] in array".to_string()),
+                    }
+                }
+            }
+            Some(b'{')=>{
+                self.position += 1;
+                let mut members = Vec::new();
+                loop{
+                    self.skip_spaces();
+                    if self.text.get(self.position) == Some(&b'}'){
+                        self.position += 1;
+                        return Ok(Value::Object(members));
+                    }
+                    let Value::Str(key) = self.value()? else{
+                        return Err("object keys must be strings".to_string());
+                    };
+                    self.skip_spaces();
+                    if self.text.get(self.position) != Some(&b':'){
+                        return Err(format!("expected : after \"{}\"", key));
+                    }
+                    self.position += 1;
+                    members.push((key, self.value()?));
+                    self.skip_spaces();
+                    match self.text.get(self.position){
+                        Some(b',')=>self.position += 1,
+                        Some(b'}')=>{}
+                        _=>return Err("expected , or } in object".to_string()),
+                    }
+                }
+            }
+            Some(b'"')=>{
+                let start = self.position+1;
+                let Some(length) = self.text[start..].iter().position(|&byte| byte == b'"') else{
+                    return Err("unterminated string".to_string());
+                };
+                self.position = start+length+1;
+                Ok(Value::Str(String::from_utf8_lossy(&self.text[start..start+length]).into_owned()))
+            }
+            Some(_)=>{
+                let start = self.position;
+                while self.position < self.text.len() && !b",]}:".contains(&self.text[self.position])
+                    && !self.text[self.position].is_ascii_whitespace(){
+                    self.position += 1;
+                }
+                let word = String::from_utf8_lossy(&self.text[start..self.position]).into_owned();
+                if word == "true" || word == "false"{
+                    return Ok(Value::Bool(word == "true"));
+                }
+                if word == "null"{
+                    return Ok(Value::Null);
+                }
+                if let Ok(int) = word.replace('_', "").parse::<i64>(){
+                    return Ok(Value::Int(int));
+                }
+                if let Ok(float) = word.replace('_', "").parse::<f64>(){
+                    return Ok(Value::Float(float));
+                }
+                Err(format!("cannot read value '{}'", word))
+            }
+            None=>Err("missing value".to_string()),
+        }
+    }
+}
+
+pub fn parse_toml(text:&str)->Result<Vec<(String, Value)>, String>{
+    // keys come back as section.key, in file order
+    let mut entries = Vec::new();
+    let mut section = String::new();
+    let mut pending = String::new();    // an array spread over several lines
+    let mut start_line = 0;
+    for (number, raw_line) in text.lines().enumerate(){
+        let line = strip_comment(raw_line).trim();
+        if pending.is_empty(){
+            if line.is_empty(){
+                continue;
+            }
+            if line.starts_with('[') && !line.contains('='){
+                section = line.trim_start_matches('[').trim_end_matches(']').trim().to_string();
+                continue;
+            }
+            start_line = number+1;
+        }
+        pending.push_str(line);
+        pending.push(' ');
+        if bracket_depth(&pending) > 0{
+            continue;
+        }
+
+        let Some((key, value_text)) = pending.split_once('=') else{
+            return Err(format!("line {}: expected key = value", start_line));
+        };
+        let key = key.trim();
+        let full_key = if section.is_empty(){key.to_string()}else{format!("{}.{}", section, key)};
+        let mut parser = ValueParser{text:value_text.as_bytes(), position:0};
+        let value = parser.value().map_err(|error| format!("line {}: {}", start_line, error))?;
+        parser.skip_spaces();
+        if parser.position != parser.text.len(){
+            return Err(format!("line {}: unexpected text after the value of {}", start_line, key));
+        }
+        entries.push((full_key, value));
+        pending.clear();
+    }
+    if !pending.is_empty(){
+        return Err(format!("line {}: array is never closed", start_line));
+    }
+    Ok(entries)
+}
+
+pub fn parse_json(text:&str)->Result<Vec<(String, Value)>, String>{
+    // the same section.key entries parse_toml gives, from {"section": {"key": value}}
+    let mut parser = ValueParser{text:text.as_bytes(), position:0};
+    let Value::Object(sections) = parser.value()? else{
+        return Err("a json parameter file must be an object".to_string());
+    };
+    parser.skip_spaces();
+    if parser.position != parser.text.len(){
+        return Err("unexpected text after the json object".to_string());
+    }
+    let mut entries = Vec::new();
+    for (section, value) in sections{
+        match value{
+            Value::Object(members)=>{
+                for (key, value) in members{
+                    entries.push((format!("{}.{}", section, key), value));
+                }
+            }
+            value=>entries.push((section, value)),
+        }
+    }
+    Ok(entries)
+}
+
+// a search or book parameter and the range it is valid in
+enum Field<'a>{
+    Flag(&'a mut bool),
+    Int(&'a mut i32, i32, i32),
+    Size(&'a mut usize, usize, usize),
+    Seed(&'a mut Seed),
+    Text(&'a mut String),
+}
+
+fn search_fields(search:&mut SearchParams)->Vec<(&'static str, Field<'_>)>{
+    let max_depth = MAX_PLY as i32;
+    vec![
+        ("depth", Field::Int(&mut search.depth, 1, max_depth)),
+        ("null_move", Field::Flag(&mut search.null_move)),
+        ("null_move_reduction", Field::Int(&mut search.null_move_reduction, 1, 6)),
+        ("null_move_min_depth", Field::Int(&mut search.null_move_min_depth, 1, max_depth)),
+        ("lmr", Field::Flag(&mut search.lmr)),
+        ("lmr_min_depth", Field::Int(&mut search.lmr_min_depth, 1, max_depth)),
+        ("lmr_min_move_index", Field::Size(&mut search.lmr_min_move_index, 0, 256)),
+        ("lmr_reduction", Field::Int(&mut search.lmr_reduction, 1, 6)),
+        ("threads", Field::Size(&mut search.threads, 1, 256)),
+        ("hash_mb", Field::Size(&mut search.hash_mb, 1, 65536)),
+        ("multi_pv", Field::Size(&mut search.multi_pv, 1, 64)),
+        ("ponder", Field::Flag(&mut search.ponder)),
+        ("skill_level", Field::Int(&mut search.skill_level, 0, skill::MAX_LEVEL)),
+        ("limit_strength", Field::Flag(&mut search.limit_strength)),
+        ("elo", Field::Int(&mut search.elo, skill::ELO_MIN, skill::ELO_MAX)),
+        ("seed", Field::Seed(&mut search.seed)),
+    ]
+}
+
+fn book_fields(book:&mut BookParams)->Vec<(&'static str, Field<'_>)>{
+    vec![
+        ("file", Field::Text(&mut book.file)),
+        ("depth", Field::Size(&mut book.depth, 0, 1000)),
+        ("best_only", Field::Flag(&mut book.best_only)),
+        ("seed", Field::Seed(&mut book.seed)),
+    ]
+}
+
+fn tablebase_fields(tablebase:&mut TablebaseParams)->Vec<(&'static str, Field<'_>)>{
+    vec![
+        ("path", Field::Text(&mut tablebase.path)),
+        ("probe_limit", Field::Size(&mut tablebase.probe_limit, 0, 7)),
+        ("probe_depth", Field::Int(&mut tablebase.probe_depth, 1, MAX_PLY as i32)),
+        ("fifty_move_rule", Field::Flag(&mut tablebase.fifty_move_rule)),
+    ]
+}
+
+fn set_field(fields:&mut [(&'static str, Field)], name:&str, value:Value)->Result<(), String>{
+    let Some((_, field)) = fields.iter_mut().find(|(field_name, _)| *field_name == name) else{
+        return Err("unknown parameter".to_string());
+    };
+    match (field, value){
+        (Field::Flag(flag), Value::Bool(value))=>**flag = value,
+        (Field::Int(target, min, max), Value::Int(value))=>{
+            if value < *min as i64 || value > *max as i64{
+                return Err(format!("{} is outside {}..{}", value, min, max));
+            }
+            **target = value as i32;
+        }
+        (Field::Size(target, min, max), Value::Int(value))=>{
+            if value < *min as i64 || value > *max as i64{
+                return Err(format!("{} is outside {}..{}", value, min, max));
+            }
+            **target = value as usize;
+        }
+        (Field::Seed(target), Value::Int(value)) if value >= 0=>**target = Seed::Fixed(value as u64),
+        (Field::Seed(target), Value::Str(value)) if value == "clock"=>**target = Seed::Clock,
+        (Field::Text(target), Value::Str(value))=>**target = value,
+        (Field::Flag(_), _)=>return Err("must be true or false".to_string()),
+        (Field::Text(_), _)=>return Err("must be a string".to_string()),
+        (Field::Seed(_), _)=>return Err("must be a positive integer or \"clock\"".to_string()),
+        _=>return Err("must be an integer".to_string()),
+    }
+    Ok(())
+}
+
+fn field_value(field:&Field)->String{
+    // the value as it is written in both file formats
+    match field{
+        Field::Flag(flag)=>flag.to_string(),
+        Field::Int(value, _, _)=>value.to_string(),
+        Field::Size(value, _, _)=>value.to_string(),
+        Field::Seed(Seed::Fixed(value))=>value.to_string(),
+        Field::Seed(Seed::Clock)=>"\"clock\"".to_string(),
+        Field::Text(text)=>format!("\"{}\"", text),
+    }
+}
+
+fn option_name(section:&str, field:&str)->String{
+    // the options every gui knows keep their usual names
+    match (section, field){
+        ("search", "hash_mb")=>"Hash".to_string(),
+        ("search", "threads")=>"Threads".to_string(),
+        ("search", "multi_pv")=>"MultiPV".to_string(),
+        ("search", "ponder")=>"Ponder".to_string(),
+        ("search", "skill_level")=>"Skill Level".to_string(),
+        ("search", "limit_strength")=>"UCI_LimitStrength".to_string(),
+        ("search", "elo")=>"UCI_Elo".to_string(),
+        ("search", "seed")=>"SkillSeed".to_string(),
+        ("book", "file")=>"BookFile".to_string(),
+        ("book", "depth")=>"BookDepth".to_string(),
+        ("book", "best_only")=>"BookBestOnly".to_string(),
+        ("book", "seed")=>"BookSeed".to_string(),
+        ("tablebase", "path")=>"SyzygyPath".to_string(),
+        ("tablebase", "probe_limit")=>"SyzygyProbeLimit".to_string(),
+        ("tablebase", "probe_depth")=>"SyzygyProbeDepth".to_string(),
+        ("tablebase", "fifty_move_rule")=>"Syzygy50MoveRule".to_string(),
+        _=>field.to_string(),
+    }
+}
+
+pub fn uci_options(params:&Params)->Vec<String>{
+    // the search and book parameters as uci option declarations
+    let mut params = params.clone();
+    let mut options = Vec::new();
+    for (section, fields) in params.sections(){
+        for (name, field) in fields{
+            let name = option_name(section, name);
+            options.push(match field{
+                Field::Flag(flag)=>format!("option name {} type check default {}", name, flag),
+                Field::Int(value, min, max)=>format!("option name {} type spin default {} min {} max {}", name, value, min, max),
+                Field::Size(value, min, max)=>format!("option name {} type spin default {} min {} max {}", name, value, min, max),
+                Field::Seed(value)=>format!("option name {} type string default {}", name, value),
+                Field::Text(text)=>format!("option name {} type string default {}", name, if text.is_empty(){"<empty>"}else{text}),
+            });
+        }
+    }
+    options
+}
+
+pub fn set_uci_option(params:&mut Params, name:&str, text:&str)->Result<(), String>{
+    // setoption with the option name from uci_options and the value as text
+    for (section, mut fields) in params.sections(){
+        let Some(index) = fields.iter().position(|(field, _)| option_name(section, field).eq_ignore_ascii_case(name)) else{
+            continue;
+        };
+        let value = match fields[index].1{
+            Field::Text(_)=>Value::Str(if text == "<empty>"{String::new()}else{text.to_string()}),
+            Field::Seed(_) if text.trim() == "clock"=>Value::Str("clock".to_string()),
+            _=>ValueParser{text:text.trim().as_bytes(), position:0}.value()?,
+        };
+        let field = fields[index].0;
+        return set_field(&mut fields, field, value).map_err(|error| format!("{}: {}", name, error));
+    }
+    Err(format!("unknown option '{}'", name))
+}
+
+// evaluation weights have to stay far below the mate range once summed up
+const MAX_WEIGHT:i32 = 5000;
+
+#[derive(Clone)]
+pub struct Params{
+    pub eval:EvalParams,
+    pub search:SearchParams,
+    pub book:BookParams,
+    pub tablebase:TablebaseParams,
+}
+
+impl Default for Params{
+    fn default()->Params{
+        Params{eval:DEFAULT_PARAMS, search:SearchParams::default(), book:BookParams::default(), tablebase:TablebaseParams::default()}
+    }
+}
+
+fn format_array(values:&[i32])->String{
+    // long arrays are the piece-square tables, written one board rank per line
+    let items:Vec<String> = values.iter().map(|value| format!("{:4}", value)).collect();
+    if values.len() <= 8{
+        return format!("[{}]", items.join(",").trim_start());
+    }
+    let rows:Vec<String> = items.chunks(8).map(|row| format!("   {},", row.join(","))).collect();
+    format!("[\n{}\n]", rows.join("\n"))
+}
+
+impl Params{
+    fn sections(&mut self)->[(&'static str, Vec<(&'static str, Field<'_>)>); 3]{
+        // the sections made of single values, eval is all arrays
+        [
+            ("search", search_fields(&mut self.search)),
+            ("book", book_fields(&mut self.book)),
+            ("tablebase", tablebase_fields(&mut self.tablebase)),
+        ]
+    }
+
+    fn section_values(&self)->Vec<(&'static str, Vec<(&'static str, String)>)>{
+        let mut params = self.clone();
+        params.sections().into_iter()
+            .map(|(section, fields)| (section, fields.iter().map(|(name, field)| (*name, field_value(field))).collect()))
+            .collect()
+    }
+
+    pub fn to_toml(&self)->String{
+        let mut text = String::new();
+        for (section, values) in self.section_values(){
+            text.push_str(&format!("[{}]\n", section));
+            for (name, value) in values{
+                text.push_str(&format!("{} = {}\n", name, value));
+            }
+            text.push('\n');
+        }
+        text.push_str("[eval]\n");
+        for (name, values) in self.eval.entries(){
+            text.push_str(&format!("{} = {}\n", name, format_array(&values)));
+        }
+        text
+    }
+
+    pub fn to_json(&self)->String{
+        let mut sections:Vec<String> = self.section_values().into_iter().map(|(section, values)|{
+            let values:Vec<String> = values.into_iter().map(|(name, value)| format!("    \"{}\": {}", name, value)).collect();
+            format!("  \"{}\": {{\n{}\n  }}", section, values.join(",\n"))
+        }).collect();
+        let eval:Vec<String> = self.eval.entries().into_iter()
+            .map(|(name, values)|{
+                let values:Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                format!("    \"{}\": [{}]", name, values.join(", "))
+            })
+            .collect();
+        sections.push(format!("  \"eval\": {{\n{}\n  }}", eval.join(",\n")));
+        format!("{{\n{}\n}}\n", sections.join(",\n"))
+    }
+
+    pub fn from_entries(entries:Vec<(String, Value)>)->Result<Params, String>{
+        // parameters missing from the file keep their default
+        let mut params = Params::default();
+        for (key, value) in entries{
+            if let Some(name) = key.strip_prefix("eval."){
+                params.set_eval(name, value).map_err(|error| format!("{}: {}", key, error))?;
+            }else if let Some(name) = key.strip_prefix("search."){
+                set_field(&mut search_fields(&mut params.search), name, value).map_err(|error| format!("{}: {}", key, error))?;
+            }else if let Some(name) = key.strip_prefix("book."){
+                set_field(&mut book_fields(&mut params.book), name, value).map_err(|error| format!("{}: {}", key, error))?;
+            }else if let Some(name) = key.strip_prefix("tablebase."){
+                set_field(&mut tablebase_fields(&mut params.tablebase), name, value).map_err(|error| format!("{}: {}", key, error))?;
+            }else{
+                return Err(format!("unknown parameter '{}'", key));
+            }
+        }
+        params.validate()?;
+        Ok(params)
+    }
+
+    fn set_eval(&mut self, name:&str, value:Value)->Result<(), String>{
+        let mut entries = self.eval.entries_mut();
+        let Some((_, slot)) = entries.iter_mut().find(|(entry_name, _)| entry_name == name) else{
+            return Err("unknown parameter".to_string());
+        };
+        let Value::Array(items) = value else{
+            return Err("must be an array of integers".to_string());
+        };
+        if items.len() != slot.len(){
+            return Err(format!("needs {} values, found {}", slot.len(), items.len()));
+        }
+        for (target, item) in slot.iter_mut().zip(items){
+            match item{
+                Value::Int(int) if int.abs() <= MAX_WEIGHT as i64=>*target = int as i32,
+                Value::Int(int)=>return Err(format!("{} is outside -{1}..{1}", int, MAX_WEIGHT)),
+                _=>return Err("must be an array of integers".to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self)->Result<(), String>{
+        // checks that need more than one value at a time
+        let eval = &self.eval;
+        if eval.mg_values[0] <= 0 || eval.eg_values[0] <= 0{
+            return Err("eval: a pawn must be worth more than nothing".to_string());
+        }
+        if eval.mg_values.iter().chain(&eval.eg_values).any(|value| *value < 0){
+            return Err("eval: material values cannot be negative".to_string());
+        }
+        if eval.king_zone_weights.iter().any(|value| *value < 0){
+            return Err("eval: king zone weights cannot be negative".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn parse(text:&str)->Result<Params, String>{
+        // json when the text is an object, toml otherwise
+        let entries = if text.trim_start().starts_with('{'){parse_json(text)?}else{parse_toml(text)?};
+        Params::from_entries(entries)
+    }
+
+    pub fn load(path:&str)->Result<Params, String>{
+        let text = std::fs::read_to_string(path).map_err(|error| format!("cannot read {}: {}", path, error))?;
+        Params::parse(&text).map_err(|error| format!("{}: {}", path, error))
+    }
+
+    pub fn save(&self, path:&str)->Result<(), String>{
+        // the format follows the file name, json for .json and toml otherwise
+        let text = if path.ends_with(".json"){self.to_json()}else{self.to_toml()};
+        std::fs::write(path, text).map_err(|error| format!("cannot write {}: {}", path, error))
+    }
+}
+
+pub fn run(args:&[String], params:&Params)->Result<(), String>{
+    // params dump [--json] [file]: writes the parameters in use, after --params
+    let json = args.iter().any(|arg| arg == "--json");
+    let rest:Vec<&String> = args.iter().filter(|arg| *arg != "--json").collect();
+    match rest.as_slice(){
+        [command] if *command == "dump"=>{
+            print!("{}", if json{params.to_json()}else{params.to_toml()});
+            Ok(())
+        }
+        [command, path] if *command == "dump"=>{
+            if json && !path.ends_with(".json"){
+                return Err("--json files need a .json name".to_string());
+            }
+            params.save(path)
+        }
+        _=>Err("usage: params dump [--json] [file]".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn changed()->Params{
+        let mut params = Params::default();
+        params.search.depth = 9;
+        params.search.null_move = false;
+        params.search.seed = Seed::Clock;
+        params.book.file = "openings.bin".to_string();
+        params.book.seed = Seed::Fixed(77);
+        params.tablebase.probe_limit = 5;
+        params.eval.mg_values[1] += 13;
+        params.eval.eg_tables[3][17] = -42;
+        params
+    }
+
+    fn error(text:&str)->String{
+        match Params::parse(text){
+            Ok(_)=>panic!("'{}' was accepted", text),
+            Err(error)=>error,
+        }
+    }
+
+    #[test]
+    fn both_formats_read_back_what_they_write(){
+        let params = changed();
+        let toml = params.to_toml();
+        let json = params.to_json();
+        assert_eq!(Params::parse(&toml).unwrap().to_toml(), toml);
+        assert_eq!(Params::parse(&json).unwrap().to_toml(), toml);
+        assert_eq!(Params::parse(&json).unwrap().to_json(), json);
+        let read = Params::parse(&toml).unwrap();
+        assert_eq!((read.search.seed, read.book.seed), (Seed::Clock, Seed::Fixed(77)));
+        assert_eq!(read.eval.eg_tables[3][17], -42);
+    }
+
+    #[test]
+    fn missing_parameters_keep_their_default(){
+        let params = Params::parse("# only one change\n[search]\ndepth = 3 # plies\n").unwrap();
+        assert_eq!(params.search.depth, 3);
+        let mut expected = Params::default();
+        expected.search.depth = 3;
+        assert_eq!(params.to_toml(), expected.to_toml());
+        assert_eq!(Params::parse("").unwrap().to_toml(), Params::default().to_toml());
+        assert_eq!(Params::parse("{\"book\": {\"seed\": \"clock\"}}").unwrap().book.seed, Seed::Clock);
+    }
+
+    #[test]
+    fn bad_parameters_are_rejected(){
+        assert!(error("[search]\ndepth = 0").contains("outside"));
+        assert!(error("[search]\ndepth = \"deep\"").contains("integer"));
+        assert!(error("[search]\nnull_move = 1").contains("true or false"));
+        assert!(error("[search]\ncolour = 1").contains("unknown parameter"));
+        assert!(error("[moves]\ndepth = 1").contains("unknown parameter"));
+        assert!(error("[book]\nseed = -3").contains("clock"));
+        assert!(error("[book]\nseed = \"sometimes\"").contains("clock"));
+        assert!(error("[eval]\nmg_values = [1, 2]").contains("needs"));
+        assert!(error("[eval]\nmg_values = [100, 300, 300, 500, 900, 99999]").contains("outside"));
+        assert!(error("[eval]\nmg_values = [0, 300, 300, 500, 900, 0]").contains("pawn"));
+        assert!(error("[eval]\nmg_values = [100, -300, 300, 500, 900, 0]").contains("negative"));
+        assert!(error("[eval]\nmg_values = [100,\n300").contains("never closed"));
+        assert!(error("{\"search\": {\"depth\": 4}} trailing").contains("unexpected text"));
+    }
+
+    #[test]
+    fn seeds_are_set_from_uci_options(){
+        let mut params = Params::default();
+        set_uci_option(&mut params, "SkillSeed", "clock").unwrap();
+        set_uci_option(&mut params, "bookseed", " 12 ").unwrap();
+        assert_eq!((params.search.seed, params.book.seed), (Seed::Clock, Seed::Fixed(12)));
+        assert!(set_uci_option(&mut params, "BookSeed", "tomorrow").is_err());
+        assert!(set_uci_option(&mut params, "Hash", "0").is_err());
+        assert!(set_uci_option(&mut params, "Colour", "1").is_err());
+        set_uci_option(&mut params, "BookFile", "<empty>").unwrap();
+        assert!(params.book.file.is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+// pawn structure evaluation, cached in a pawn hash table
+// the structure only changes on pawn moves and captures, so most positions
+// in a search share their entry with many others
+
+use std::cell::RefCell;
+
+use crate::Board;
+use crate::eval::{self, EvalParams};
+
+const FILE_H:u64 = 0x0101_0101_0101_0101;
+
+// default weights, (middlegame, endgame) in centipawns
+pub const DOUBLED:[i32; 2] = [-10, -20];       // per extra pawn on a file
+pub const ISOLATED:[i32; 2] = [-10, -15];
+pub const BACKWARD:[i32; 2] = [-8, -10];
+// indexed by rank counted from the own side, a pawn on the 7th is about to promote
+pub const PASSED_MG:[i32; 8] = [0, 5, 10, 15, 25, 40, 60, 0];
+pub const PASSED_EG:[i32; 8] = [0, 10, 20, 35, 60, 90, 130, 0];
+
+const PAWN_HASH_SIZE:usize = 1<<14;
+
+#[derive(Clone, Copy, Default)]
+struct PawnEntry{
+    white_pawns:u64,
+    black_pawns:u64,
+    mg:i32,
+    eg:i32,
+}
+
+thread_local!{
+    // one table per search thread, so lazy smp threads never contend on it
+    static PAWN_HASH:RefCell<Vec<PawnEntry>> = RefCell::new(vec![PawnEntry::default(); PAWN_HASH_SIZE]);
+}
+
+fn file_mask(square:u32)->u64{
+    FILE_H << (square%8)
+}
+
+fn adjacent_files(square:u32)->u64{
+    let file = file_mask(square);
+    (file<<1 & !FILE_H) | (file>>1 & !(FILE_H<<7))
+}
+
+fn ranks_ahead(square:u32, white:bool)->u64{
+    // every square on the ranks in front of the pawn, from its own point of view
+    let rank = square/8;
+    if white{
+        return if rank >= 7{0}else{!0u64 << (8*(rank+1))};
+    }
+    (1u64 << (8*rank)) - 1
+}
+
+fn pawn_attacks(pawns:u64, white:bool)->u64{
+    if white{
+        return (pawns<<9 & !FILE_H)|(pawns<<7 & !(FILE_H<<7));
+    }
+    (pawns>>7 & !FILE_H)|(pawns>>9 & !(FILE_H<<7))
+}
+
+fn side_structure(own:u64, enemy:u64, p:&EvalParams, white:bool)->(i32, i32){
+    let mut mg = 0;
+    let mut eg = 0;
+    let enemy_attacks = pawn_attacks(enemy, !white);
+
+    for file in 0..8{
+        let on_file = (own & FILE_H<<file).count_ones() as i32;
+        if on_file > 1{
+            mg += p.doubled[0]*(on_file-1);
+            eg += p.doubled[1]*(on_file-1);
+        }
+    }
+
+    let mut pawns = own;
+    while pawns != 0{
+        let square = pawns.trailing_zeros();
+        let neighbours = adjacent_files(square);
+        let ahead = ranks_ahead(square, white);
+
+        if own & neighbours == 0{
+            mg += p.isolated[0];
+            eg += p.isolated[1];
+        }else{
+            // no friendly pawn beside or behind it can ever defend it,
+            // and an enemy pawn already controls the square in front
+            let stop_square = if white{1u64<<(square+8)}else{1u64<<(square-8)};
+            if own & neighbours & !ahead == 0 && stop_square & enemy_attacks != 0{
+                mg += p.backward[0];
+                eg += p.backward[1];
+            }
+        }
+
+        if enemy & (file_mask(square)|neighbours) & ahead == 0{
+            let relative_rank = if white{square/8}else{7-square/8} as usize;
+            mg += p.passed_mg[relative_rank];
+            eg += p.passed_eg[relative_rank];
+        }
+        pawns &= pawns-1;
+    }
+    (mg, eg)
+}
+
+pub fn side_pawn_structure(board:&Board, p:&EvalParams, white:bool)->(i32, i32){
+    // one side only and bypassing the hash, for the eval trace
+    let (own, enemy) = if white{(board.whites, board.blacks)}else{(board.blacks, board.whites)};
+    side_structure(board.pawns & own, board.pawns & enemy, p, white)
+}
+
+pub fn pawn_structure(board:&Board)->(i32, i32){
+    // middlegame and endgame score, black minus white.
+    // the hash is only valid for one set of weights, so this always uses the
+    // engine wide ones
+    let p = eval::params();
+    let white_pawns = board.pawns & board.whites;
+    let black_pawns = board.pawns & board.blacks;
+    let key = white_pawns.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ black_pawns.rotate_left(29).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    let index = (key >> 50) as usize % PAWN_HASH_SIZE;
+
+    PAWN_HASH.with(|table|{
+        let mut table = table.borrow_mut();
+        let entry = table[index];
+        if entry.white_pawns == white_pawns && entry.black_pawns == black_pawns{
+            return (entry.mg, entry.eg);
+        }
+        let (white_mg, white_eg) = side_structure(white_pawns, black_pawns, p, true);
+        let (black_mg, black_eg) = side_structure(black_pawns, white_pawns, p, false);
+        let (mg, eg) = (black_mg-white_mg, black_eg-white_eg);
+        table[index] = PawnEntry{white_pawns, black_pawns, mg, eg};
+        (mg, eg)
+    })
+}
@@ -0,0 +1,105 @@
+// reading games from pgn files: the tag pairs and the moves of the main line,
+// comments, variations, move numbers and annotation glyphs are skipped
+
+pub struct PgnGame{
+    pub tags:Vec<(String, String)>,
+    pub moves:Vec<String>,      // in SAN, as written
+    pub result:String,          // 1-0, 0-1, 1/2-1/2 or *
+}
+
+impl PgnGame{
+    pub fn tag(&self, name:&str)->Option<&str>{
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+}
+
+const RESULTS:[&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+fn parse_tag(line:&str)->Option<(String, String)>{
+    // [Name "value"]
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name.to_string(), value.replace("\\\"", "\"")))
+}
+
+pub fn parse_pgn(text:&str)->Vec<PgnGame>{
+    let mut games = Vec::new();
+    let mut game = PgnGame{tags:Vec::new(), moves:Vec::new(), result:"*".to_string()};
+    let mut comment = false;        // inside { }
+    let mut variation = 0;          // depth of ( )
+    let mut finished = false;       // the result was read, the next tag starts a game
+    for line in text.lines(){
+        let line = line.trim();
+        if !comment && variation == 0 && line.starts_with('['){
+            if finished || !game.moves.is_empty(){
+                games.push(std::mem::replace(&mut game, PgnGame{tags:Vec::new(), moves:Vec::new(), result:"*".to_string()}));
+                finished = false;
+            }
+            if let Some(tag) = parse_tag(line){
+                game.tags.push(tag);
+            }
+            continue;
+        }
+        if !comment && line.starts_with('%'){
+            continue;
+        }
+        // braces and parentheses can touch the moves, so they get their own tokens
+        let spaced = line.replace('{', " { ").replace('}', " } ").replace('(', " ( ").replace(')', " ) ");
+        for token in spaced.split_whitespace(){
+            match token{
+                "{"=>comment = true,
+                "}"=>comment = false,
+                _ if comment=>{}
+                ";"=>break,
+                _ if token.starts_with(';')=>break,
+                "("=>variation += 1,
+                ")"=>variation -= 1,
+                _ if variation > 0=>{}
+                _ if token.starts_with('$')=>{}
+                _ if RESULTS.contains(&token)=>{
+                    game.result = token.to_string();
+                    finished = true;
+                }
+                _=>{
+                    // 12. and 12... in front of a move, sometimes without a space
+                    let san = token.trim_start_matches(|symbol:char| symbol.is_ascii_digit() || symbol == '.');
+                    if !san.is_empty(){
+                        game.moves.push(san.to_string());
+                    }
+                }
+            }
+        }
+    }
+    if finished || !game.moves.is_empty(){
+        games.push(game);
+    }
+    games
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn games_are_split_at_tags_and_keep_only_the_main_line(){
+        let games = parse_pgn(r#"[Event "one"]
+[White "A \"B\" C"]
+
+1. e4 {best by test} e5 2.Nf3 (2. f4 exf4 {gambit}) 2... Nc6 $1 ; a comment
+3. Bb5 1-0
+
+[Event "two"]
+% an escaped line
+1.d4 d5 *
+"#);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("one"));
+        assert_eq!(games[0].tag("White"), Some("A \"B\" C"));
+        assert_eq!(games[0].moves, ["e4", "e5", "Nf3", "Nc6", "Bb5"]);
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[1].moves, ["d4", "d5"]);
+        assert_eq!(games[1].result, "*");
+        assert_eq!(games[1].tag("White"), None);
+    }
+}
@@ -0,0 +1,55 @@
+// a small seedable random number generator (splitmix64), for choices that
+// should vary between games but repeat for a fixed seed
+
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Seed{
+    Fixed(u64),     // every run makes the same choices
+    Clock,          // a different seed every run, written "clock"
+}
+
+impl Default for Seed{
+    fn default()->Seed{
+        // runs are reproducible unless the clock is asked for
+        Seed::Fixed(1)
+    }
+}
+
+impl fmt::Display for Seed{
+    fn fmt(&self, f:&mut fmt::Formatter)->fmt::Result{
+        match self{
+            Seed::Fixed(seed)=>write!(f, "{}", seed),
+            Seed::Clock=>write!(f, "clock"),
+        }
+    }
+}
+
+pub struct Random{
+    state:u64,
+}
+
+impl Random{
+    pub fn new(seed:Seed)->Random{
+        match seed{
+            Seed::Fixed(seed)=>Random{state:seed},
+            Seed::Clock=>{
+                let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(1, |time| time.as_nanos() as u64);
+                Random{state:nanos}
+            }
+        }
+    }
+
+    pub fn next_u64(&mut self)->u64{
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn below(&mut self, limit:u64)->u64{
+        // uniform enough for small limits
+        self.next_u64() % limit.max(1)
+    }
+}
@@ -0,0 +1,176 @@
+// search and evaluation scores in centipawns, from the point of view of the
+// side to move. the arithmetic saturates at INFINITY so negating or widening a
+// window can never wrap around. the range is split into bands that never overlap,
+// each mirrored below zero:
+//     0 ..= DRAW_BAND              draws, tablebase draws saved or spoiled by the fifty move rule
+//     DRAW_BAND+1 ..= MAX_EVAL     evaluations
+//     TB_WIN-MAX_PLY ..= TB_WIN    tablebase wins, nearer ones higher
+//     MATE-MAX_PLY ..= MATE        mates, nearer ones higher
+
+use std::fmt;
+use std::ops::{Add, Neg, Sub};
+
+use crate::search::MAX_PLY;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct Score(i16);
+
+impl Score{
+    pub const INFINITY:Score = Score(30000);
+    pub const MATE:Score = Score(29000);    // mate at the root, every ply further away scores one less
+    pub const DRAW:Score = Score(0);
+    pub const DRAW_BAND:i16 = 1;            // reserved for draws just off zero, evaluations skip it
+    pub const TB_WIN:Score = Score(20000);  // a tablebase win, far above evaluations and below the mates
+    // everything above this is reserved for tablebase and mate scores, evaluations are clamped below it
+    pub const MAX_EVAL:Score = Score(20000 - MAX_PLY - 1);
+
+    pub const fn new(centipawns:i16)->Score{
+        Score(centipawns)
+    }
+
+    pub fn from_eval(centipawns:i32)->Score{
+        // static evaluations stay in their band, clear of draws and of tablebase and mate scores
+        let limit = Score::MAX_EVAL.0 as i32;
+        let centipawns = centipawns.clamp(-limit, limit) as i16;
+        if centipawns != 0 && centipawns.abs() <= Score::DRAW_BAND{
+            return Score((Score::DRAW_BAND + 1)*centipawns.signum());
+        }
+        Score(centipawns)
+    }
+
+    pub fn mated_in(ply:i16)->Score{
+        // the side to move is checkmated, ply moves from the root
+        -Score::MATE + ply
+    }
+
+    pub fn centipawns(self)->i16{
+        self.0
+    }
+
+    pub fn is_mate(self)->bool{
+        self.0.abs() >= Score::MATE.0 - MAX_PLY
+    }
+
+    pub fn is_decisive(self)->bool{
+        // a mate or a tablebase win or loss, scored by its distance from the root
+        self.0.abs() > Score::MAX_EVAL.0
+    }
+
+    pub fn uci(self)->String{
+        // cp in centipawns, or mate in moves, negative when the side to move is mated
+        if self.is_mate(){
+            let plies = Score::MATE.0 - self.0.abs();
+            if self.0 > 0{
+                return format!("mate {}", (plies+1)/2);
+            }
+            return format!("mate -{}", plies/2);
+        }
+        format!("cp {}", self.0)
+    }
+
+    pub fn xboard(self)->i32{
+        // centipawns, mates as 100000 plus the moves to mate like most engines
+        if self.is_mate(){
+            let plies = (Score::MATE.0 - self.0.abs()) as i32;
+            if self.0 > 0{
+                return 100000 + (plies+1)/2;
+            }
+            return -100000 - plies/2;
+        }
+        self.0 as i32
+    }
+}
+
+// the bands must not overlap
+const _:() = assert!(Score::DRAW_BAND < Score::MAX_EVAL.0 && Score::MAX_EVAL.0 < Score::TB_WIN.0 - MAX_PLY);
+const _:() = assert!(Score::TB_WIN.0 < Score::MATE.0 - MAX_PLY && Score::MATE.0 < Score::INFINITY.0);
+
+impl Neg for Score{
+    type Output = Score;
+    fn neg(self)->Score{
+        // the range is symmetric, so this cannot overflow
+        Score(-self.0)
+    }
+}
+
+fn saturate(value:i32)->Score{
+    let limit = Score::INFINITY.0 as i32;
+    Score(value.clamp(-limit, limit) as i16)
+}
+
+impl Add<i16> for Score{
+    type Output = Score;
+    fn add(self, rhs:i16)->Score{
+        saturate(self.0 as i32 + rhs as i32)
+    }
+}
+
+impl Sub<i16> for Score{
+    type Output = Score;
+    fn sub(self, rhs:i16)->Score{
+        saturate(self.0 as i32 - rhs as i32)
+    }
+}
+
+impl fmt::Display for Score{
+    fn fmt(&self, f:&mut fmt::Formatter)->fmt::Result{
+        // mate scores are shown as the number of moves until mate
+        if self.is_mate(){
+            let plies = Score::MATE.0 - self.0.abs();
+            if self.0 > 0{
+                return write!(f, "mate in {}", (plies+1)/2);
+            }
+            return write!(f, "mated in {}", plies/2);
+        }
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn evaluations_stay_below_tablebase_and_mate_scores(){
+        for centipawns in [i32::MAX, 1_000_000, Score::TB_WIN.0 as i32, Score::MATE.0 as i32]{
+            let score = Score::from_eval(centipawns);
+            assert_eq!(score, Score::MAX_EVAL);
+            assert_eq!(Score::from_eval(-centipawns), -Score::MAX_EVAL);
+            assert!(!score.is_decisive() && !score.is_mate());
+        }
+        assert_eq!(Score::from_eval(i32::MIN), -Score::MAX_EVAL);
+        assert!(Score::MAX_EVAL < Score::TB_WIN - MAX_PLY);
+        assert!((Score::TB_WIN - MAX_PLY).is_decisive() && !Score::TB_WIN.is_mate());
+        assert!(Score::mated_in(MAX_PLY).is_mate() && (-Score::mated_in(MAX_PLY)).is_mate());
+    }
+
+    #[test]
+    fn evaluations_skip_the_draw_band(){
+        assert_eq!(Score::from_eval(0), Score::DRAW);
+        assert_eq!(Score::from_eval(Score::DRAW_BAND as i32), Score::new(Score::DRAW_BAND + 1));
+        assert_eq!(Score::from_eval(-(Score::DRAW_BAND as i32)), Score::new(-Score::DRAW_BAND - 1));
+        assert_eq!(Score::from_eval(Score::DRAW_BAND as i32 + 1), Score::new(Score::DRAW_BAND + 1));
+    }
+
+    #[test]
+    fn arithmetic_saturates_at_infinity(){
+        assert_eq!(Score::INFINITY + 1, Score::INFINITY);
+        assert_eq!(-Score::INFINITY - 1, -Score::INFINITY);
+        assert_eq!(Score::MAX_EVAL + i16::MAX, Score::INFINITY);
+    }
+
+    #[test]
+    fn mates_are_shown_in_moves(){
+        // mated at the root is 0 plies, mating with the next move 1 ply
+        assert_eq!(Score::mated_in(0).uci(), "mate -0");
+        assert_eq!((-Score::mated_in(1)).uci(), "mate 1");
+        assert_eq!((-Score::mated_in(3)).uci(), "mate 2");
+        assert_eq!(Score::mated_in(4).uci(), "mate -2");
+        assert_eq!((-Score::mated_in(3)).xboard(), 100002);
+        assert_eq!(Score::mated_in(4).xboard(), -100002);
+        assert_eq!(Score::new(-35).uci(), "cp -35");
+        assert_eq!(Score::TB_WIN.uci(), "cp 20000");
+        assert_eq!(Score::new(-35).xboard(), -35);
+        assert_eq!((-Score::mated_in(3)).to_string(), "mate in 2");
+    }
+}
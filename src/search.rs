@@ -0,0 +1,595 @@
+// alpha-beta search over the positions produced by the find_new_*_move generators
+// scores are from the point of view of the side to move (negamax)
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::endgame;
+use crate::nnue::{self, Accumulator, Network};
+use crate::notation::{pv_to_san, uci_move, Move};
+use crate::random::Seed;
+use crate::score::Score;
+use crate::skill;
+use crate::syzygy::{self, Tablebase};
+use crate::tt::{zobrist, score_from_tt, score_to_tt, Bound, TranspositionTable, TtEntry};
+use crate::{
+    Board, find_new_pawn_move, find_new_white_pawn_move, find_new_knight_move,
+    find_new_bishop_move, find_new_rook_move, find_new_queen_move, find_new_king_move,
+};
+
+pub const MAX_PLY:i16 = 128;
+
+#[derive(Clone, Copy)]
+pub struct SearchParams{
+    // every selectivity feature can be switched off on its own,
+    // so its contribution can be measured in engine matches
+    pub depth:i32,                  // in plies, including the engines own move
+    pub null_move:bool,
+    pub null_move_reduction:i32,    // the null move is searched at depth-1-R
+    pub null_move_min_depth:i32,
+    pub lmr:bool,
+    pub lmr_min_depth:i32,
+    pub lmr_min_move_index:usize,   // moves ordered before this are never reduced
+    pub lmr_reduction:i32,
+    pub threads:usize,              // lazy smp, 1 keeps the search deterministic
+    pub hash_mb:usize,              // size of the shared transposition table
+    pub multi_pv:usize,             // root moves searched with their own score and pv
+    pub ponder:bool,                // think on the opponents time about the expected reply
+    pub skill_level:i32,            // 0 to 20, below 20 the engine plays weaker on purpose
+    pub limit_strength:bool,        // take the level from elo instead of skill_level
+    pub elo:i32,
+    pub seed:Seed,                  // for the moves a weakened engine picks
+}
+
+impl Default for SearchParams{
+    fn default()->SearchParams{
+        SearchParams {
+            depth: 5,
+            null_move: true,
+            null_move_reduction: 2,
+            null_move_min_depth: 3,
+            lmr: true,
+            lmr_min_depth: 3,
+            lmr_min_move_index: 3,
+            lmr_reduction: 1,
+            threads: 1,
+            hash_mb: 16,
+            multi_pv: 1,
+            ponder: false,
+            skill_level: skill::MAX_LEVEL,
+            limit_strength: false,
+            elo: skill::ELO_MIN,
+            seed: Seed::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct SearchStats{
+    pub nodes:u64,
+    pub qnodes:u64,         // nodes resolved by static evaluation at the horizon
+    pub tt_hits:u64,
+    pub cutoffs:u64,        // beta cutoffs, including null move cutoffs
+    pub tb_hits:u64,        // positions scored by the tablebases
+    pub seldepth:i16,       // deepest ply reached, reductions and all
+    pub time:Duration,
+    pub ebf:f64,            // nodes of the last iteration over nodes of the one before
+}
+
+impl SearchStats{
+    pub fn nps(&self)->u64{
+        let millis = self.time.as_millis().max(1) as u64;
+        self.nodes*1000/millis
+    }
+
+    pub fn merge(&mut self, other:&SearchStats){
+        // adds the counters of a helper thread
+        self.nodes += other.nodes;
+        self.qnodes += other.qnodes;
+        self.tt_hits += other.tt_hits;
+        self.cutoffs += other.cutoffs;
+        self.tb_hits += other.tb_hits;
+        self.seldepth = self.seldepth.max(other.seldepth);
+    }
+}
+
+pub struct PvLine{
+    pub score:Score,
+    pub pv:Vec<Board>,
+}
+
+pub struct SearchResult{
+    pub board:Board,        // position after the chosen move, its eval is the search score
+    pub pv:Vec<Board>,      // positions along the principal variation, starting with board
+    pub lines:Vec<PvLine>,  // the best multi_pv root moves, best first, lines[0] is board and pv
+    pub depth:i32,
+    pub stats:SearchStats,
+}
+
+#[derive(Clone, Copy)]
+pub struct SearchLimits{
+    pub depth:i32,
+    pub movetime:Option<Duration>,
+}
+
+const MOVES_TO_GO:u64 = 30;         // assumed when the gui only sends the clock
+const MOVE_OVERHEAD:u64 = 50;       // milliseconds kept back for the gui and the pipe
+
+pub fn allocate_time(time:u64, increment:u64, moves_to_go:Option<u64>)->Duration{
+    // the time for one move with time milliseconds left on the clock: an even
+    // share of the clock plus most of the increment, never more than is left
+    let moves = moves_to_go.unwrap_or(MOVES_TO_GO).max(1);
+    let budget = time/moves + increment*3/4;
+    Duration::from_millis(budget.min(time.saturating_sub(MOVE_OVERHEAD)).max(1))
+}
+
+pub fn stop_after(stop:Arc<AtomicBool>, time:Duration){
+    // sets stop once time has passed, for searches whose clock starts late like a ponder hit
+    std::thread::spawn(move ||{
+        std::thread::sleep(time);
+        stop.store(true, Ordering::Relaxed);
+    });
+}
+
+pub struct Searcher<'a>{
+    pub params:&'a SearchParams,
+    pub tt:&'a TranspositionTable,
+    pub stop:&'a AtomicBool,    // shared by all threads searching the same position
+    pub deadline:Option<Instant>,
+    pub node_limit:Option<u64>,
+    pub stats:SearchStats,
+    network:Option<&'static Network>,
+    accumulators:Vec<Accumulator>,     // one per ply, the current line of the search
+    tablebase:Option<Arc<Tablebase>>,
+}
+
+impl<'a> Searcher<'a>{
+    pub fn new(params:&'a SearchParams, tt:&'a TranspositionTable, stop:&'a AtomicBool)->Searcher<'a>{
+        let network = nnue::network();
+        let accumulators = match network{
+            Some(network)=>(0..=MAX_PLY).map(|_| network.empty_accumulator()).collect(),
+            None=>Vec::new(),
+        };
+        Searcher{params, tt, stop, deadline:None, node_limit:None, stats:SearchStats::default(), network, accumulators, tablebase:syzygy::tablebase()}
+    }
+
+    fn push_accumulator(&mut self, parent:&Board, child:&Board, ply:i16){
+        // make step of the network, the child accumulator at ply+1 is derived
+        // from the one at ply. unmaking is just returning to the lower ply
+        if let Some(network) = self.network{
+            let (done, rest) = self.accumulators.split_at_mut(ply as usize + 1);
+            network.update(&done[ply as usize], &mut rest[0], parent, child);
+        }
+    }
+
+    fn evaluate(&self, board:&Board, white:bool, ply:i16)->Score{
+        if let Some(network) = self.network{
+            if let Some(score) = endgame::evaluate(board, white){
+                return score;
+            }
+            debug_assert_eq!(self.accumulators[ply as usize], network.refresh(board), "incremental accumulator out of sync");
+            return network.evaluate(&self.accumulators[ply as usize], white);
+        }
+        board.clone().evaluate_position(white)
+    }
+
+    fn stopped(&self)->bool{
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    fn check_time(&self){
+        if let Some(deadline) = self.deadline{
+            if self.stats.nodes.is_multiple_of(1024) && Instant::now() >= deadline{
+                self.stop.store(true, Ordering::Relaxed);
+            }
+        }
+        if self.node_limit.is_some_and(|limit| self.stats.nodes >= limit){
+            self.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn search_root(&mut self, board:&Board, white:bool, depth:i32, excluded:&[Move])->Option<(Score, Vec<Board>)>{
+        // one iteration at a fixed depth, returns the score and the pv
+        // or None if the search was stopped before the iteration finished.
+        // the excluded root moves are skipped, multi-pv finds its later lines that way
+        let hash = zobrist(board, white);
+        let tt_move = self.tt.probe(hash).and_then(|entry| entry.best_move);
+        let mut moves = generate_moves(board, white);
+        order_tt_move(board, white, &mut moves, tt_move);
+
+        if let Some(network) = self.network{
+            self.accumulators[0] = network.refresh(board);
+        }
+        let mut alpha = -Score::INFINITY;
+        let mut best_pv:Vec<Board> = Vec::new();
+        let mut child_pv:Vec<Board> = Vec::new();
+        for (child, _) in moves.iter(){
+            if child.in_check(white) || excluded.contains(&Move::between(board, child, white)){
+                continue;
+            }
+            child_pv.clear();
+            self.push_accumulator(board, child, 0);
+            let score = -self.alpha_beta(child, !white, depth-1, -Score::INFINITY, -alpha, 1, true, &mut child_pv);
+            if self.stopped(){
+                return None;
+            }
+            if score > alpha || best_pv.is_empty(){
+                alpha = score;
+                best_pv.clear();
+                best_pv.push(*child);
+                best_pv.append(&mut child_pv);
+            }
+        }
+        if best_pv.is_empty(){
+            return None;
+        }
+        if !excluded.is_empty(){
+            // a later line is not the best move of this position
+            return Some((alpha, best_pv));
+        }
+        self.tt.store(hash, TtEntry{
+            score:score_to_tt(alpha, 0),
+            depth,
+            bound:Bound::Exact,
+            best_move:Some(Move::between(board, &best_pv[0], white)),
+        });
+        Some((alpha, best_pv))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn alpha_beta(&mut self, board:&Board, white:bool, depth:i32, mut alpha:Score, beta:Score, ply:i16, allow_null:bool, pv:&mut Vec<Board>)->Score{
+        // pv is filled with the line of positions that produced the returned score
+        self.stats.nodes += 1;
+        self.stats.seldepth = self.stats.seldepth.max(ply);
+        self.check_time();
+        if self.stopped(){
+            return Score::DRAW;
+        }
+        let params = self.params;
+        let color_map = if white{board.whites}else{board.blacks};
+        if depth <= 0 || ply >= MAX_PLY{
+            self.stats.qnodes += 1;
+            return self.evaluate(board, white, ply);
+        }
+
+        // transposition table cutoffs are only taken in null window nodes,
+        // so the pv is not cut short
+        let hash = zobrist(board, white);
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.probe(hash){
+            self.stats.tt_hits += 1;
+            tt_move = entry.best_move;
+            if entry.depth >= depth && alpha+1 == beta{
+                let score = score_from_tt(entry.score, ply);
+                match entry.bound{
+                    Bound::Exact=>return score.clamp(alpha, beta),
+                    Bound::Lower if score >= beta=>return beta,
+                    Bound::Upper if score <= alpha=>return alpha,
+                    _=>{}
+                }
+            }
+        }
+
+        // tablebase positions get their exact result, but only right after a capture
+        // or pawn move, before the fifty move counter could have changed it
+        if let Some(tablebase) = &self.tablebase{
+            if board.fifty_rule == 0 && tablebase.probes(board, depth){
+                if let Some(wdl) = tablebase.probe_wdl(board, white){
+                    self.stats.tb_hits += 1;
+                    let score = tablebase.score(wdl, ply);
+                    self.tt.store(hash, TtEntry{score:score_to_tt(score, ply), depth, bound:Bound::Exact, best_move:None});
+                    return score.clamp(alpha, beta);
+                }
+            }
+        }
+        let in_check = board.in_check(white);
+
+        // null move: let the opponent move twice, if we still fail high the
+        // position is good enough to cut without a full search. skipped in check
+        // and when only pawns are left, where zugzwang makes passing unsound
+        let has_pieces = color_map & (board.knights|board.bishops|board.rooks|board.queens) != 0;
+        if params.null_move && allow_null && !in_check && has_pieces && depth >= params.null_move_min_depth{
+            let mut null_board = *board;
+            null_board.en_passant_index = 0;
+            self.push_accumulator(board, &null_board, ply);
+            let score = -self.alpha_beta(&null_board, !white, depth-1-params.null_move_reduction, -beta, -beta+1, ply+1, false, &mut Vec::new());
+            if self.stopped(){
+                return Score::DRAW;
+            }
+            if score >= beta{
+                self.stats.cutoffs += 1;
+                return beta;
+            }
+        }
+
+        let mut moves = generate_moves(board, white);
+        order_tt_move(board, white, &mut moves, tt_move);
+        let original_alpha = alpha;
+        let mut best_move = None;
+        let mut legal_moves = 0;
+        let mut child_pv:Vec<Board> = Vec::new();
+        for (child, capture_score) in moves.iter(){
+            if child.in_check(white){
+                continue;
+            }
+            let index = legal_moves;
+            legal_moves += 1;
+
+            // late move reductions: quiet moves ordered late are searched shallower
+            // with a null window first, and only re-searched if they beat alpha
+            let reduce = params.lmr && !in_check && *capture_score == 0
+                && depth >= params.lmr_min_depth && index >= params.lmr_min_move_index
+                && !child.in_check(!white);
+
+            child_pv.clear();
+            self.push_accumulator(board, child, ply);
+            let mut score;
+            if reduce{
+                score = -self.alpha_beta(child, !white, depth-1-params.lmr_reduction, -alpha-1, -alpha, ply+1, true, &mut child_pv);
+                if score > alpha{
+                    child_pv.clear();
+                    score = -self.alpha_beta(child, !white, depth-1, -beta, -alpha, ply+1, true, &mut child_pv);
+                }
+            }else{
+                score = -self.alpha_beta(child, !white, depth-1, -beta, -alpha, ply+1, true, &mut child_pv);
+            }
+            if self.stopped(){
+                return Score::DRAW;
+            }
+
+            if score >= beta{
+                self.stats.cutoffs += 1;
+                self.tt.store(hash, TtEntry{
+                    score:score_to_tt(beta, ply),
+                    depth,
+                    bound:Bound::Lower,
+                    best_move:Some(Move::between(board, child, white)),
+                });
+                return beta;
+            }
+            if score > alpha{
+                alpha = score;
+                best_move = Some(Move::between(board, child, white));
+                pv.clear();
+                pv.push(*child);
+                pv.append(&mut child_pv);
+            }
+        }
+
+        if legal_moves == 0{
+            // checkmate is scored by distance, so faster mates and slower losses are preferred
+            let score = if in_check{Score::mated_in(ply)}else{Score::DRAW};
+            return score.clamp(alpha, beta);
+        }
+        self.tt.store(hash, TtEntry{
+            score:score_to_tt(alpha, ply),
+            depth,
+            bound:if alpha > original_alpha{Bound::Exact}else{Bound::Upper},
+            best_move,
+        });
+        alpha
+    }
+
+    pub fn iterative_deepening(&mut self, board:&Board, white:bool, limits:&SearchLimits, thread_index:usize, mut report:Option<&mut dyn FnMut(&SearchResult)>)->Option<SearchResult>{
+        // helper threads (thread_index > 0) search every other iteration one ply
+        // deeper, so the threads spread over different depths and fill the
+        // shared table with results the main thread can reuse.
+        // report is called after every finished iteration
+        let start = Instant::now();
+        let mut best:Option<SearchResult> = None;
+        let mut previous_nodes = 0;
+        // the first iteration always runs to completion so there is a move to play
+        let deadline = self.deadline.take();
+        let node_limit = self.node_limit.take();
+        // with the root in the tablebases only the moves they rank best are searched,
+        // and the score is theirs unless the search finds a mate
+        let root = self.tablebase.as_ref().and_then(|tablebase| tablebase.root_moves(board, white));
+        if root.is_some(){
+            self.stats.tb_hits += 1;
+        }
+        for depth in 1..=limits.depth{
+            let search_depth = if thread_index % 2 == 1{depth+1}else{depth};
+            let nodes_before = self.stats.nodes;
+            let mut lines:Vec<PvLine> = Vec::new();
+            let mut excluded:Vec<Move> = root.as_ref().map(|(excluded, _)| excluded.clone()).unwrap_or_default();
+            while lines.len() < self.params.multi_pv.max(1){
+                // None with the stop flag clear just means there are no more moves
+                let Some((score, pv)) = self.search_root(board, white, search_depth, &excluded) else{
+                    break;
+                };
+                excluded.push(Move::between(board, &pv[0], white));
+                lines.push(PvLine{score, pv});
+            }
+            if lines.is_empty() || self.stopped(){
+                break;
+            }
+            if let Some((_, score)) = &root{
+                for line in lines.iter_mut().filter(|line| !line.score.is_mate()){
+                    line.score = *score;
+                }
+            }
+            // the lines are found best first except when a score changed between searches
+            lines.sort_by_key(|line| std::cmp::Reverse(line.score));
+            let (score, pv) = (lines[0].score, lines[0].pv.clone());
+            let iteration_nodes = self.stats.nodes - nodes_before;
+            if previous_nodes > 0{
+                self.stats.ebf = iteration_nodes as f64 / previous_nodes as f64;
+            }
+            previous_nodes = iteration_nodes;
+            self.stats.time = start.elapsed();
+
+            let mut chosen = pv[0];
+            chosen.eval = score;
+            let result = SearchResult{board:chosen, pv, lines, depth:search_depth, stats:self.stats};
+            if let Some(report) = report.as_mut(){
+                report(&result);
+            }
+            best = Some(result);
+            self.deadline = deadline;
+            self.node_limit = node_limit;
+        }
+        best
+    }
+}
+
+fn order_tt_move(board:&Board, white:bool, moves:&mut [(Board, i16)], tt_move:Option<Move>){
+    // the best move from an earlier search of this position is tried first
+    if let Some(tt_move) = tt_move{
+        if let Some(index) = moves.iter().position(|(child, _)| Move::between(board, child, white) == tt_move){
+            moves[..=index].rotate_right(1);
+        }
+    }
+}
+
+impl SearchResult{
+    pub fn info_line(&self, board:&Board, white:bool)->String{
+        // one line summary of the search, the pv is given in SAN
+        let stats = &self.stats;
+        format!("depth {} seldepth {} score {} nodes {} qnodes {} nps {} tthits {} cutoffs {} ebf {:.2} time {}ms pv {}",
+            self.depth, stats.seldepth, self.board.eval, stats.nodes, stats.qnodes,
+            stats.nps(), stats.tt_hits, stats.cutoffs, stats.ebf, stats.time.as_millis(),
+            pv_to_san(board, &self.pv, white))
+    }
+
+    pub fn uci_info(&self, board:&Board, white:bool)->String{
+        // the same summary as uci info lines, one per multi-pv line
+        let stats = &self.stats;
+        let mut info:Vec<String> = Vec::with_capacity(self.lines.len());
+        for (index, line) in self.lines.iter().enumerate(){
+            let mut pv:Vec<String> = Vec::with_capacity(line.pv.len());
+            let mut previous = board;
+            let mut side = white;
+            for position in &line.pv{
+                pv.push(uci_move(previous, position, side));
+                previous = position;
+                side = !side;
+            }
+            let multi_pv = if self.lines.len() > 1{format!(" multipv {}", index+1)}else{String::new()};
+            info.push(format!("info depth {} seldepth {}{} score {} nodes {} nps {} tbhits {} time {} pv {}",
+                self.depth, stats.seldepth, multi_pv, line.score.uci(), stats.nodes,
+                stats.nps(), stats.tb_hits, stats.time.as_millis(), pv.join(" ")));
+        }
+        info.join("\n")
+    }
+
+    pub fn lines_text(&self, board:&Board, white:bool)->String{
+        // the multi-pv lines numbered best first, with their scores and pvs in SAN
+        let lines:Vec<String> = self.lines.iter().enumerate()
+            .map(|(index, line)| format!("{:2}. {:>12}  {}", index+1, line.score.to_string(), pv_to_san(board, &line.pv, white)))
+            .collect();
+        lines.join("\n")
+    }
+}
+
+pub fn generate_moves(board:&Board, white:bool)->Vec<(Board, i16)>{
+    // every position reachable in one move, paired with its MVV-LVA score
+    // and sorted so captures of valuable pieces are searched first
+    let color_map = if white{board.whites}else{board.blacks};
+    let mut moves:Vec<Board> = Vec::with_capacity(48);
+
+    let mut pawn_bitmap:u64 = 0;
+    if white{
+        while let Some(new_board) = find_new_white_pawn_move(board, &mut pawn_bitmap){
+            moves.push(new_board);
+        }
+    }else{
+        while let Some(new_board) = find_new_pawn_move(board, &mut pawn_bitmap){
+            moves.push(new_board);
+        }
+    }
+    let mut knight_bitmap:u64 = 0;
+    while let Some(new_board) = find_new_knight_move(board, &mut knight_bitmap, color_map){
+        moves.push(new_board);
+    }
+    let mut bishop_bitmap:u64 = 0;
+    while let Some(new_board) = find_new_bishop_move(board, &mut bishop_bitmap, color_map){
+        moves.push(new_board);
+    }
+    let mut rook_bitmap:u64 = 0;
+    while let Some(new_board) = find_new_rook_move(board, &mut rook_bitmap, color_map){
+        moves.push(new_board);
+    }
+    let mut queen_bitmap:u64 = 0;
+    while let Some(new_board) = find_new_queen_move(board, &mut queen_bitmap, color_map){
+        moves.push(new_board);
+    }
+    let mut king_bitmap:u64 = 0;
+    while let Some(new_board) = find_new_king_move(board, &mut king_bitmap, color_map){
+        moves.push(new_board);
+    }
+
+    let mut scored:Vec<(Board, i16)> = moves.into_iter()
+        .map(|mut new_board| {
+            new_board.update_tables(board);
+            let score = capture_score(board, &new_board, white);
+            (new_board, score)
+        })
+        .collect();
+    scored.sort_by_key(|scored_move| std::cmp::Reverse(scored_move.1));
+    scored
+}
+
+fn capture_score(board:&Board, new_board:&Board, white:bool)->i16{
+    // most valuable victim, least valuable attacker. zero for quiet moves
+    let (own_before, own_after, enemy_before, enemy_after) = if white{
+        (board.whites, new_board.whites, board.blacks, new_board.blacks)
+    }else{
+        (board.blacks, new_board.blacks, board.whites, new_board.whites)
+    };
+    let victim = enemy_before & !enemy_after;
+    if victim == 0{
+        return 0;
+    }
+    let attacker = own_before & !own_after;
+    10*piece_value(board, victim) - piece_value(board, attacker) + 10
+}
+
+fn piece_value(board:&Board, square:u64)->i16{
+    if board.pawns & square != 0{
+        1
+    }else if board.knights & square != 0 || board.bishops & square != 0{
+        3
+    }else if board.rooks & square != 0{
+        5
+    }else if board.queens & square != 0{
+        9
+    }else{
+        200
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::find_best_move;
+    use crate::notation::{parse_fen, pv_to_san};
+
+    fn search(fen:&str, depth:i32)->(Score, String){
+        let (board, white) = parse_fen(fen).unwrap();
+        let tt = TranspositionTable::new(1);
+        let limits = SearchLimits{depth, movetime:None};
+        let result = find_best_move(board, white, &limits, &SearchParams::default(), &tt, &AtomicBool::new(false), None);
+        (result.board.eval, pv_to_san(&board, &result.pv, white))
+    }
+
+    #[test]
+    fn mates_are_scored_by_their_distance(){
+        let (score, pv) = search("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4", 3);
+        assert_eq!((score, pv.as_str()), (-Score::mated_in(1), "Qxf7#"));
+        assert_eq!(score.uci(), "mate 1");
+
+        // the ladder takes two moves, whatever black does in between
+        let (score, pv) = search("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 4);
+        assert_eq!(score, -Score::mated_in(3));
+        assert!(pv.ends_with('#'), "{}", pv);
+    }
+
+    #[test]
+    fn the_mated_side_delays_the_mate(){
+        // black has only Kg8, then Rb8 mates
+        let (score, _) = search("7k/R7/8/8/8/8/8/1R4K1 b - - 0 1", 4);
+        assert_eq!(score, Score::mated_in(2));
+        assert_eq!(score.uci(), "mate -1");
+    }
+}
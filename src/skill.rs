@@ -0,0 +1,79 @@
+// weaker play on purpose, for opponents the full search is too strong for.
+// a skill level from 0 to 20, or a target elo mapped onto it, limits the depth
+// and the nodes of the search and picks among the best few root moves at
+// random, the lower the level the more often a worse one. 20 is full strength.
+// the elo scale is uncalibrated: UCI_Elo is spread linearly over the levels
+// between ELO_MIN and ELO_MAX, a guess that no match results back up. the
+// repository has no match runner, so treat the numbers as relative strength only
+
+use crate::random::Random;
+use crate::search::{SearchParams, SearchResult};
+
+pub const MAX_LEVEL:i32 = 20;
+pub const ELO_MIN:i32 = 800;        // level 0, uncalibrated
+pub const ELO_MAX:i32 = 2200;       // level 19, the strongest level below full strength, uncalibrated
+pub const LINES:usize = 4;          // root moves searched to choose from
+
+pub struct Skill{
+    level:f64,                      // fractional, so every elo plays a little differently
+}
+
+impl Skill{
+    pub fn new(params:&SearchParams)->Option<Skill>{
+        // None at full strength
+        let level = if params.limit_strength{
+            (MAX_LEVEL-1) as f64 * (params.elo - ELO_MIN) as f64 / (ELO_MAX - ELO_MIN) as f64
+        }else{
+            params.skill_level as f64
+        };
+        if level >= MAX_LEVEL as f64{
+            return None;
+        }
+        Some(Skill{level:level.max(0.0)})
+    }
+
+    pub fn depth(&self)->i32{
+        1 + (self.level/2.0) as i32
+    }
+
+    pub fn nodes(&self)->u64{
+        // doubles every two levels, from 100 at level 0
+        (100.0 * 2f64.powf(self.level/2.0)) as u64
+    }
+
+    pub fn pick(&self, result:&mut SearchResult, random:&mut Random){
+        // plays one of the lines instead of the best: every line is pushed up by most
+        // of its distance to the best, plus a random part that grows with the weakness
+        // and the spread of the lines, and the highest one is played. so close moves
+        // are mixed up freely while clearly bad ones stay rare. mates are always played
+        let lines = &result.lines;
+        if lines.len() < 2 || lines[0].score.is_mate(){
+            return;
+        }
+        let top = lines[0].score.centipawns() as f64;
+        let spread = (top - lines[lines.len()-1].score.centipawns() as f64).min(100.0);
+        let weakness = 120.0 - 2.0*self.level;
+        let mut best = 0;
+        let mut best_score = f64::MIN;
+        for (index, line) in lines.iter().enumerate(){
+            let score = line.score.centipawns() as f64;
+            let push = (weakness*(top - score) + spread*random.below(weakness as u64) as f64) / 128.0;
+            if score + push >= best_score{
+                best_score = score + push;
+                best = index;
+            }
+        }
+        let line = &lines[best];
+        result.board = line.pv[0];
+        result.board.eval = line.score;
+        result.pv = line.pv.clone();
+    }
+}
+
+pub fn choose(params:&SearchParams, result:&mut SearchResult, random:&mut Random){
+    // the move to play from a finished search, the best one at full strength.
+    // random lives as long as the game, so a fixed seed replays the same game
+    if let Some(skill) = Skill::new(params){
+        skill.pick(result, random);
+    }
+}
@@ -0,0 +1,1130 @@
+// syzygy endgame tablebases. the win/draw/loss tables (.rtbw) say how a
+// position ends with best play, the distance to zero tables (.rtbz) how many
+// plies it takes to the next capture or pawn move on the way there, which is
+// what the fifty move rule counts. tables are found by their material in the
+// directories of the path and read into memory the first time they are probed.
+//
+// a table turns a position into an index by placing the pieces group by group,
+// using the symmetries of the board, and stores the values in blocks compressed
+// by recursive pairing with a canonical huffman code. the decoding follows the
+// reference probing code of the format.
+//
+// castling and en passant are ignored: the tables hold neither, and this
+// engine never generates castling or en passant moves either
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::eval::piece_maps;
+use crate::notation::{legal_moves, parse_fen, san, Move, START_FEN};
+use crate::score::Score;
+use crate::Board;
+
+const PIECES:usize = 7;             // the largest tables there are
+const MAX_DTZ:i32 = 1<<18;          // above every distance to zero, for ranking root moves
+
+const WDL_MAGIC:[u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC:[u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+// flags of a compressed table
+const STM:u8 = 1;                   // dtz: the side to move the table is for
+const MAPPED:u8 = 2;                // dtz: values go through the value map
+const WIN_PLIES:u8 = 4;             // dtz: wins are stored in plies, not moves
+const LOSS_PLIES:u8 = 8;
+const WIDE:u8 = 16;                 // dtz: the value map has 16 bit entries
+const SINGLE_VALUE:u8 = 128;        // every position has the same value
+
+// the dtz value map used for a loss, blessed loss, draw, cursed win and win
+const WDL_MAP:[usize; 5] = [1, 3, 0, 2, 0];
+
+pub const WDL_NAMES:[&str; 5] = ["loss", "blessed loss", "draw", "cursed win", "win"];
+
+// squares in the tables count from a1, a1 = 0 and h8 = 63
+fn file_of(square:usize)->usize{
+    square & 7
+}
+
+fn rank_of(square:usize)->usize{
+    square >> 3
+}
+
+fn off_diagonal(square:usize)->i32{
+    // positive above the a1-h8 diagonal, negative below it
+    rank_of(square) as i32 - file_of(square) as i32
+}
+
+struct Indices{
+    binomial:[[u64; 64]; PIECES],       // binomial[k][n] ways to choose k of n squares
+    triangle:[usize; 64],               // the a1-d1-d4 triangle to 0..9, the diagonal last
+    below_diagonal:[usize; 64],         // squares below the a1-h8 diagonal to 0..27
+    kings:[[usize; 64]; 10],            // the 462 placements of two kings, the first in the triangle
+    pawns:[usize; 64],                  // a2-h7 to 0..47, the leading pawn has the highest value
+    lead_pawns:[[u64; 64]; PIECES],     // index of the leading pawn, per number of leading pawns
+    lead_pawns_size:[[u64; 4]; PIECES], // leading pawn placements per file
+}
+
+impl Indices{
+    fn new()->Indices{
+        let mut binomial = [[0u64; 64]; PIECES];
+        for n in 0..64{
+            binomial[0][n] = 1;
+            for k in 1..PIECES{
+                if n > 0{
+                    binomial[k][n] = binomial[k-1][n-1] + binomial[k][n-1];
+                }
+            }
+        }
+
+        let mut below_diagonal = [0; 64];
+        for (code, square) in (0..64).filter(|&square| off_diagonal(square) < 0).enumerate(){
+            below_diagonal[square] = code;
+        }
+
+        let in_triangle = |square:usize| square < 32 && file_of(square) <= 3 && off_diagonal(square) <= 0;
+        let mut triangle = [0; 64];
+        let below = (0..64).filter(|&square| in_triangle(square) && off_diagonal(square) < 0);
+        let on = (0..64).filter(|&square| in_triangle(square) && off_diagonal(square) == 0);
+        for (code, square) in below.chain(on).enumerate(){
+            triangle[square] = code;
+        }
+
+        // kings next to each other are left out, and with the first king on the
+        // diagonal the second one is never above it. both on the diagonal come last
+        let mut kings = [[0; 64]; 10];
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for (index, row) in kings.iter_mut().enumerate(){
+            for first in (0..64).filter(|&square| in_triangle(square) && triangle[square] == index){
+                for (second, slot) in row.iter_mut().enumerate(){
+                    if file_of(first).abs_diff(file_of(second)) <= 1 && rank_of(first).abs_diff(rank_of(second)) <= 1{
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) > 0{
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) == 0{
+                        both_on_diagonal.push((index, second));
+                    }else{
+                        *slot = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal{
+            kings[index][second] = code;
+            code += 1;
+        }
+        debug_assert_eq!(code, 462);
+
+        // pawns are numbered file by file from the edge, rank 2 first, so the
+        // other pawns of a group are never nearer the edge than the leading one
+        let mut pawns = [0; 64];
+        let mut lead_pawns = [[0u64; 64]; PIECES];
+        let mut lead_pawns_size = [[0u64; 4]; PIECES];
+        let mut available = 48;
+        for count in 1..=5{
+            for (file, size) in lead_pawns_size[count].iter_mut().enumerate(){
+                let mut index = 0;
+                for rank in 1..7{
+                    let square = 8*rank + file;
+                    if count == 1{
+                        available -= 1;
+                        pawns[square] = available;
+                        available -= 1;
+                        pawns[square ^ 7] = available;
+                    }
+                    lead_pawns[count][square] = index;
+                    index += binomial[count-1][pawns[square]];
+                }
+                *size = index;
+            }
+        }
+        Indices{binomial, triangle, below_diagonal, kings, pawns, lead_pawns, lead_pawns_size}
+    }
+}
+
+fn indices()->&'static Indices{
+    static INDICES:OnceLock<Indices> = OnceLock::new();
+    INDICES.get_or_init(Indices::new)
+}
+
+// reads past the end give zeros, truncated files are caught by the size check
+fn byte(bytes:&[u8], at:usize)->u8{
+    bytes.get(at).copied().unwrap_or(0)
+}
+
+fn read_u16(bytes:&[u8], at:usize)->u16{
+    u16::from_le_bytes([byte(bytes, at), byte(bytes, at+1)])
+}
+
+fn read_u32(bytes:&[u8], at:usize)->u32{
+    u32::from_le_bytes([byte(bytes, at), byte(bytes, at+1), byte(bytes, at+2), byte(bytes, at+3)])
+}
+
+fn read_u32_be(bytes:&[u8], at:usize)->u32{
+    u32::from_be_bytes([byte(bytes, at), byte(bytes, at+1), byte(bytes, at+2), byte(bytes, at+3)])
+}
+
+fn read_u64_be(bytes:&[u8], at:usize)->u64{
+    (read_u32_be(bytes, at) as u64) << 32 | read_u32_be(bytes, at+4) as u64
+}
+
+// every symbol of the pairing tree is three bytes, a 12 bit left and right symbol
+fn tree_left(bytes:&[u8], tree:usize, symbol:usize)->usize{
+    let at = tree + 3*symbol;
+    ((byte(bytes, at+1) & 0xf) as usize) << 8 | byte(bytes, at) as usize
+}
+
+fn tree_right(bytes:&[u8], tree:usize, symbol:usize)->usize{
+    let at = tree + 3*symbol;
+    (byte(bytes, at+2) as usize) << 4 | (byte(bytes, at+1) >> 4) as usize
+}
+
+fn symbol_length(bytes:&[u8], tree:usize, symbol:usize, lengths:&mut [u32], visited:&mut [bool])->u32{
+    // the number of values a symbol expands to, minus one. the tree has no cycles
+    visited[symbol] = true;
+    let right = tree_right(bytes, tree, symbol);
+    if right == 0xfff{
+        return 0;
+    }
+    let left = tree_left(bytes, tree, symbol);
+    for child in [left, right]{
+        if child < lengths.len() && !visited[child]{
+            lengths[child] = symbol_length(bytes, tree, child, lengths, visited);
+        }
+    }
+    lengths.get(left).copied().unwrap_or(0) + lengths.get(right).copied().unwrap_or(0) + 1
+}
+
+#[derive(Clone, Default)]
+struct Pairs{
+    // one compressed table: per side to move in wdl tables, per leading pawn file with pawns
+    flags:u8,
+    min_symbol_length:u8,       // the value itself in single value tables
+    block_size:usize,
+    span:u64,                   // there is a sparse index entry every span values
+    blocks:usize,
+    lowest_symbol:usize,        // the offsets are into the bytes of the table
+    tree:usize,
+    sparse_index:usize,
+    sparse_index_size:usize,
+    block_lengths:usize,
+    block_lengths_size:usize,
+    data:usize,
+    base:Vec<u64>,              // the lowest code of every symbol length, padded to 64 bits
+    symbol_length:Vec<u32>,
+    pieces:[u8; PIECES],        // the order the pieces are encoded in
+    group_index:[u64; PIECES+1],
+    group_length:[usize; PIECES+1], // ends with a zero
+    value_map:[usize; 4],       // dtz only, see WDL_MAP
+}
+
+struct Table{
+    bytes:Vec<u8>,
+    dtz:bool,
+    white:String,               // the material of the side the table calls white
+    symmetric:bool,             // both sides have the same material
+    has_pawns:bool,
+    unique_pieces:bool,         // a side has a piece other than the king just once
+    pawn_count:[usize; 2],      // the leading side first
+    piece_count:usize,
+    pairs:Vec<Vec<Pairs>>,      // [side to move][leading pawn file]
+}
+
+impl Table{
+    fn load(name:&str, path:&Path, dtz:bool)->Result<Table, String>{
+        let bytes = std::fs::read(path).map_err(|error| format!("cannot read {}: {}", path.display(), error))?;
+        let magic = if dtz{DTZ_MAGIC}else{WDL_MAGIC};
+        if bytes.len() < 5 || bytes[..4] != magic{
+            return Err(format!("{} is not a syzygy table", path.display()));
+        }
+        let Some((white, black)) = name.split_once('v') else{
+            return Err(format!("{} is not a syzygy table name", name));
+        };
+        let count = |side:&str, piece:char| side.chars().filter(|&symbol| symbol == piece).count();
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        // the leading side is the one with fewer pawns, or the only one with pawns
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let mut table = Table{
+            bytes,
+            dtz,
+            white:white.to_string(),
+            symmetric:white == black,
+            has_pawns:white_pawns + black_pawns > 0,
+            unique_pieces:[white, black].iter().any(|side| "PNBRQ".chars().any(|piece| count(side, piece) == 1)),
+            pawn_count:if white_leads{[white_pawns, black_pawns]}else{[black_pawns, white_pawns]},
+            piece_count:white.len() + black.len(),
+            pairs:Vec::new(),
+        };
+        table.read_header().map_err(|error| format!("{}: {}", path.display(), error))?;
+        Ok(table)
+    }
+
+    fn read_header(&mut self)->Result<(), String>{
+        let flags = self.bytes[4];
+        if (flags & 2 != 0) != self.has_pawns || (flags & 1 != 0) == self.symmetric || self.piece_count > PIECES{
+            return Err("the header does not match the material".to_string());
+        }
+        let sides = if !self.dtz && !self.symmetric{2}else{1};
+        let files = if self.has_pawns{4}else{1};
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut pairs = vec![vec![Pairs::default(); files]; sides];
+
+        // per file the order of the groups and the pieces, one nibble per side
+        let mut at = 5;
+        for file in 0..files{
+            let first = byte(&self.bytes, at);
+            let second = if both_pawns{byte(&self.bytes, at+1)}else{0xff};
+            let order = [[(first & 0xf) as usize, (second & 0xf) as usize], [(first >> 4) as usize, (second >> 4) as usize]];
+            at += 1 + both_pawns as usize;
+            for k in 0..self.piece_count{
+                let pieces = byte(&self.bytes, at);
+                for (side, side_pairs) in pairs.iter_mut().enumerate(){
+                    side_pairs[file].pieces[k] = if side == 0{pieces & 0xf}else{pieces >> 4};
+                }
+                at += 1;
+            }
+            for (side, side_pairs) in pairs.iter_mut().enumerate(){
+                self.set_groups(&mut side_pairs[file], order[side], file);
+            }
+        }
+        at += at & 1;
+
+        for file in 0..files{
+            for side_pairs in pairs.iter_mut(){
+                at = self.set_sizes(&mut side_pairs[file], at)?;
+            }
+        }
+        if self.dtz{
+            for file_pairs in pairs[0].iter_mut(){
+                if file_pairs.flags & MAPPED == 0{
+                    continue;
+                }
+                if file_pairs.flags & WIDE != 0{
+                    at += at & 1;
+                    for index in 0..4{
+                        file_pairs.value_map[index] = at + 2;
+                        at += 2*read_u16(&self.bytes, at) as usize + 2;
+                    }
+                }else{
+                    for index in 0..4{
+                        file_pairs.value_map[index] = at + 1;
+                        at += byte(&self.bytes, at) as usize + 1;
+                    }
+                }
+            }
+            at += at & 1;
+        }
+        for file in 0..files{
+            for side_pairs in pairs.iter_mut(){
+                side_pairs[file].sparse_index = at;
+                at += 6*side_pairs[file].sparse_index_size;
+            }
+        }
+        for file in 0..files{
+            for side_pairs in pairs.iter_mut(){
+                side_pairs[file].block_lengths = at;
+                at += 2*side_pairs[file].block_lengths_size;
+            }
+        }
+        for file in 0..files{
+            for side_pairs in pairs.iter_mut(){
+                at = (at + 63) & !63;
+                side_pairs[file].data = at;
+                at += side_pairs[file].blocks*side_pairs[file].block_size;
+            }
+        }
+        if at > self.bytes.len(){
+            return Err("the file is truncated".to_string());
+        }
+        self.pairs = pairs;
+        Ok(())
+    }
+
+    fn set_groups(&self, pairs:&mut Pairs, order:[usize; 2], file:usize){
+        // the pieces are placed in groups: the leading pawns or the first two or three
+        // pieces, then runs of the same piece. the index is a mixed radix number with
+        // the groups in the order the table gives, order[1] is the other sides pawns
+        let indices = indices();
+        let mut n = 0;
+        let mut first_length = if self.has_pawns{0}else if self.unique_pieces{3}else{2};
+        pairs.group_length[0] = 1;
+        for i in 1..self.piece_count{
+            first_length -= 1;
+            if first_length > 0 || pairs.pieces[i] == pairs.pieces[i-1]{
+                pairs.group_length[n] += 1;
+            }else{
+                n += 1;
+                pairs.group_length[n] = 1;
+            }
+        }
+        n += 1;
+        pairs.group_length[n] = 0;
+
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = if both_pawns{2}else{1};
+        let mut free_squares = 64 - pairs.group_length[0] - if both_pawns{pairs.group_length[1]}else{0};
+        let mut index:u64 = 1;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1]{
+            if k == order[0]{
+                pairs.group_index[0] = index;
+                index *= if self.has_pawns{
+                    indices.lead_pawns_size[pairs.group_length[0]][file]
+                }else if self.unique_pieces{
+                    31332
+                }else{
+                    462
+                };
+            }else if k == order[1]{
+                pairs.group_index[1] = index;
+                index *= indices.binomial[pairs.group_length[1]][48 - pairs.group_length[0]];
+            }else{
+                pairs.group_index[next] = index;
+                index *= indices.binomial[pairs.group_length[next]][free_squares];
+                free_squares -= pairs.group_length[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        pairs.group_index[n] = index;
+    }
+
+    fn set_sizes(&self, pairs:&mut Pairs, at:usize)->Result<usize, String>{
+        let bytes = &self.bytes;
+        pairs.flags = byte(bytes, at);
+        if pairs.flags & SINGLE_VALUE != 0{
+            pairs.min_symbol_length = byte(bytes, at+1);
+            return Ok(at+2);
+        }
+        let (block_bits, span_bits) = (byte(bytes, at+1), byte(bytes, at+2));
+        let max_length = byte(bytes, at+8);
+        pairs.min_symbol_length = byte(bytes, at+9);
+        if block_bits >= 32 || span_bits >= 63 || max_length > 64 || max_length < pairs.min_symbol_length{
+            return Err("bad block sizes".to_string());
+        }
+        let groups = pairs.group_length.iter().position(|&length| length == 0).unwrap_or(PIECES);
+        let size = pairs.group_index[groups];
+        pairs.block_size = 1 << block_bits;
+        pairs.span = 1 << span_bits;
+        pairs.sparse_index_size = size.div_ceil(pairs.span) as usize;
+        pairs.blocks = read_u32(bytes, at+4) as usize;
+        // padding keeps the sparse index from pointing past the block lengths
+        pairs.block_lengths_size = pairs.blocks + byte(bytes, at+3) as usize;
+
+        // canonical huffman: longer codes have lower values, so the lowest code of every
+        // length, shifted to the top of 64 bits, tells the length of the code in front
+        let mut at = at+10;
+        pairs.lowest_symbol = at;
+        let lengths = (max_length - pairs.min_symbol_length) as usize + 1;
+        let lowest = |index:usize| read_u16(bytes, pairs.lowest_symbol + 2*index) as u64;
+        let mut base = vec![0u64; lengths];
+        for index in (0..lengths-1).rev(){
+            base[index] = (base[index+1] + lowest(index)).wrapping_sub(lowest(index+1)) / 2;
+        }
+        for (index, value) in base.iter_mut().enumerate(){
+            *value = value.checked_shl(64 - (index + pairs.min_symbol_length as usize) as u32).unwrap_or(0);
+        }
+        pairs.base = base;
+        at += 2*lengths;
+
+        let symbols = read_u16(bytes, at) as usize;
+        at += 2;
+        pairs.tree = at;
+        pairs.symbol_length = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols{
+            if !visited[symbol]{
+                pairs.symbol_length[symbol] = symbol_length(bytes, pairs.tree, symbol, &mut pairs.symbol_length, &mut visited);
+            }
+        }
+        Ok(at + 3*symbols + (symbols & 1))
+    }
+
+    fn decompress(&self, pairs:&Pairs, index:u64)->i32{
+        // the value at index: the sparse index gives a block and an offset near it,
+        // then the symbols of the block are skipped until the one holding the value
+        // and the pairing tree is walked down to it
+        let bytes = &self.bytes;
+        if pairs.flags & SINGLE_VALUE != 0{
+            return pairs.min_symbol_length as i32;
+        }
+        let entry = pairs.sparse_index + 6*(index / pairs.span) as usize;
+        let mut block = read_u32(bytes, entry) as i64;
+        let mut offset = read_u16(bytes, entry+4) as i64 + (index % pairs.span) as i64 - (pairs.span / 2) as i64;
+        let block_length = |block:i64| read_u16(bytes, pairs.block_lengths + 2*block as usize) as i64;
+        while offset < 0{
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block){
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        let length_of = |symbol:usize| pairs.symbol_length.get(symbol).copied().unwrap_or(0) as i64;
+        let min_length = pairs.min_symbol_length as usize;
+        let mut at = pairs.data + block as usize * pairs.block_size;
+        let mut buffer = read_u64_be(bytes, at);
+        at += 8;
+        let mut buffer_size:i32 = 64;
+        let mut symbol;
+        loop{
+            let mut length = 0;
+            while length+1 < pairs.base.len() && buffer < pairs.base[length]{
+                length += 1;
+            }
+            let code = buffer.wrapping_sub(pairs.base[length]).checked_shr(64 - (length + min_length) as u32).unwrap_or(0);
+            symbol = code as usize + read_u16(bytes, pairs.lowest_symbol + 2*length) as usize;
+            if offset < length_of(symbol) + 1{
+                break;
+            }
+            offset -= length_of(symbol) + 1;
+            length += min_length;
+            buffer = buffer.checked_shl(length as u32).unwrap_or(0);
+            buffer_size -= length as i32;
+            if buffer_size <= 32{
+                buffer_size += 32;
+                buffer |= (read_u32_be(bytes, at) as u64).checked_shl((64 - buffer_size) as u32).unwrap_or(0);
+                at += 4;
+            }
+        }
+        while length_of(symbol) != 0{
+            let left = tree_left(bytes, pairs.tree, symbol);
+            if offset < length_of(left) + 1{
+                symbol = left;
+            }else{
+                offset -= length_of(left) + 1;
+                symbol = tree_right(bytes, pairs.tree, symbol);
+            }
+        }
+        tree_left(bytes, pairs.tree, symbol) as i32
+    }
+
+    fn probe(&self, board:&Board, white:bool, wdl:i32)->Option<i32>{
+        // the stored value of the position: wdl from -2 to 2, or the dtz for a
+        // position known to have the result wdl. None when a dtz table only has
+        // the other side to move
+        let indices = indices();
+        // the tables are for white having the material named first. otherwise, and
+        // for black to move in symmetric tables, the colours are swapped and the
+        // board is turned upside down
+        let flip = (self.symmetric && !white) || material(board, board.whites) != self.white;
+        let (flip_colour, flip_squares) = if flip{(8, 56)}else{(0, 0)};
+        let side = (flip == white) as usize;   // 1 when the tables black is to move
+
+        let position = pieces(board);
+        let mut squares = [0usize; PIECES];
+        let mut codes = [0u8; PIECES];
+        let mut size = 0;
+        let mut lead_pawns = 0;
+        let mut file = 0;
+        let lead_code = self.pairs[0][0].pieces[0] ^ flip_colour;
+        if self.has_pawns{
+            for &(square, code) in &position{
+                if code == lead_code{
+                    squares[size] = square ^ flip_squares;
+                    size += 1;
+                }
+            }
+            lead_pawns = size;
+            // the leading pawn is the one nearest the edge, the lowest of those on its file
+            let lead = (0..lead_pawns).max_by_key(|&index| indices.pawns[squares[index]])?;
+            squares.swap(0, lead);
+            file = file_of(squares[0]).min(7 - file_of(squares[0]));
+        }
+        let pairs = &self.pairs[if self.pairs.len() == 2{side}else{0}][file];
+        // symmetric tables without pawns hold both sides to move
+        if self.dtz && (pairs.flags & STM) as usize != side && (self.has_pawns || !self.symmetric){
+            return None;
+        }
+        for &(square, code) in &position{
+            if self.has_pawns && code == lead_code{
+                continue;
+            }
+            squares[size] = square ^ flip_squares;
+            codes[size] = code ^ flip_colour;
+            size += 1;
+        }
+
+        // the pieces in the order of the table, the leading piece on files a-d
+        for i in lead_pawns..size.saturating_sub(1){
+            if let Some(j) = (i+1..size).find(|&j| codes[j] == pairs.pieces[i]){
+                codes.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+        if file_of(squares[0]) > 3{
+            for square in &mut squares[..size]{
+                *square ^= 7;
+            }
+        }
+
+        let mut index;
+        if self.has_pawns{
+            index = indices.lead_pawns[lead_pawns][squares[0]];
+            squares[1..lead_pawns].sort_by_key(|&square| indices.pawns[square]);
+            for (i, &square) in squares.iter().enumerate().take(lead_pawns).skip(1){
+                index += indices.binomial[i][indices.pawns[square]];
+            }
+        }else{
+            // without pawns the board is also mirrored so the leading piece is below
+            // rank 5, and the first leading piece off the a1-h8 diagonal is below it
+            if rank_of(squares[0]) > 3{
+                for square in &mut squares[..size]{
+                    *square ^= 56;
+                }
+            }
+            for i in 0..pairs.group_length[0]{
+                if off_diagonal(squares[i]) == 0{
+                    continue;
+                }
+                if off_diagonal(squares[i]) > 0{
+                    for square in &mut squares[i..size]{
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+            index = if self.unique_pieces{
+                unique_index(&squares)
+            }else{
+                indices.kings[indices.triangle[squares[0]]][squares[1]] as u64
+            };
+        }
+
+        // the other groups, each as a combination of the squares the earlier groups left
+        index *= pairs.group_index[0];
+        let mut start = pairs.group_length[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while pairs.group_length[next] != 0{
+            let length = pairs.group_length[next];
+            squares[start..start+length].sort_unstable();
+            let mut group = 0;
+            for i in 0..length{
+                let square = squares[start+i];
+                let adjust = squares[..start].iter().filter(|&&other| square > other).count();
+                group += indices.binomial[i+1][square - adjust - if remaining_pawns{8}else{0}];
+            }
+            remaining_pawns = false;
+            index += group*pairs.group_index[next];
+            start += length;
+            next += 1;
+        }
+
+        let value = self.decompress(pairs, index);
+        if !self.dtz{
+            return Some(value - 2);
+        }
+        Some(self.dtz_value(pairs, value, wdl))
+    }
+
+    fn dtz_value(&self, pairs:&Pairs, value:i32, wdl:i32)->i32{
+        // dtz values can be stored through a map, and in moves instead of plies
+        let mut value = value;
+        if pairs.flags & MAPPED != 0{
+            let start = pairs.value_map[WDL_MAP[(wdl+2) as usize]];
+            value = if pairs.flags & WIDE != 0{
+                read_u16(&self.bytes, start + 2*value as usize) as i32
+            }else{
+                byte(&self.bytes, start + value as usize) as i32
+            };
+        }
+        let in_moves = match wdl{
+            2=>pairs.flags & WIN_PLIES == 0,
+            -2=>pairs.flags & LOSS_PLIES == 0,
+            0=>false,
+            _=>true,
+        };
+        if in_moves{
+            value *= 2;
+        }
+        value + 1
+    }
+}
+
+fn unique_index(squares:&[usize])->u64{
+    // the first three pieces together: the first in the a1-d1-d4 triangle, and
+    // while pieces are on the diagonal the next one is not above it
+    let indices = indices();
+    let (first, second, third) = (squares[0], squares[1], squares[2]);
+    let adjust_second = (second > first) as usize;
+    let adjust_third = (third > first) as usize + (third > second) as usize;
+    let index = if off_diagonal(first) != 0{
+        (indices.triangle[first]*63 + second - adjust_second)*62 + third - adjust_third
+    }else if off_diagonal(second) != 0{
+        (6*63 + rank_of(first)*28 + indices.below_diagonal[second])*62 + third - adjust_third
+    }else if off_diagonal(third) != 0{
+        6*63*62 + 4*28*62 + rank_of(first)*7*28 + (rank_of(second) - adjust_second)*28 + indices.below_diagonal[third]
+    }else{
+        6*63*62 + 4*28*62 + 4*7*28 + rank_of(first)*7*6 + (rank_of(second) - adjust_second)*6 + rank_of(third) - adjust_third
+    };
+    index as u64
+}
+
+fn pieces(board:&Board)->Vec<(usize, u8)>{
+    // every piece with its square counted from a1 and its code in the tables,
+    // pawn 1 to king 6 plus 8 for black
+    let maps = piece_maps(board);
+    let mut pieces = Vec::with_capacity(PIECES);
+    for square in 0..64{
+        let mask = 1u64 << (square ^ 7);    // the board counts files from h
+        let colour = if board.whites & mask != 0{0}else if board.blacks & mask != 0{8}else{continue};
+        let kind = maps.iter().position(|map| map & mask != 0).unwrap_or(5) as u8 + 1;
+        pieces.push((square, colour | kind));
+    }
+    pieces
+}
+
+fn material(board:&Board, colour:u64)->String{
+    // the king, then the other pieces from queen to pawn, as in the table names
+    let mut text = String::new();
+    for (letter, map) in [('K', board.kings), ('Q', board.queens), ('R', board.rooks), ('B', board.bishops), ('N', board.knights), ('P', board.pawns)]{
+        for _ in 0..(map & colour).count_ones(){
+            text.push(letter);
+        }
+    }
+    text
+}
+
+fn valid_name(name:&str)->bool{
+    let Some((white, black)) = name.split_once('v') else{
+        return false;
+    };
+    name.len() - 1 <= PIECES
+        && [white, black].iter().all(|side| side.starts_with('K') && side[1..].chars().all(|piece| "QRBNP".contains(piece)))
+}
+
+fn is_capture(board:&Board, child:&Board)->bool{
+    (child.whites | child.blacks).count_ones() < (board.whites | board.blacks).count_ones()
+}
+
+fn is_mate(board:&Board, white:bool)->bool{
+    board.in_check(white) && legal_moves(board, white).is_empty()
+}
+
+fn dtz_before_zeroing(wdl:i32)->i32{
+    // the dtz of a position whose best move is a capture or pawn move with result wdl
+    match wdl{
+        2=>1,
+        1=>101,
+        -1=>-101,
+        -2=>-1,
+        _=>0,
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TablebaseParams{
+    pub path:String,            // directories with .rtbw and .rtbz files, separated like PATH, empty for none
+    pub probe_limit:usize,      // only positions with at most this many pieces are probed
+    pub probe_depth:i32,        // in search, positions at the limit are only probed from this depth
+    pub fifty_move_rule:bool,   // wins the fifty move rule turns into draws are scored as draws
+}
+
+impl Default for TablebaseParams{
+    fn default()->TablebaseParams{
+        TablebaseParams{path:String::new(), probe_limit:PIECES, probe_depth:1, fifty_move_rule:true}
+    }
+}
+
+struct TableFile{
+    path:PathBuf,
+    table:OnceLock<Option<Table>>,  // read on the first probe, None if the file is broken
+}
+
+impl TableFile{
+    fn table(&self, name:&str, dtz:bool)->Option<&Table>{
+        self.table.get_or_init(|| match Table::load(name, &self.path, dtz){
+            Ok(table)=>Some(table),
+            Err(error)=>{
+                eprintln!("{}", error);
+                None
+            }
+        }).as_ref()
+    }
+}
+
+pub struct Tablebase{
+    params:TablebaseParams,
+    wdl:HashMap<String, TableFile>,
+    dtz:HashMap<String, TableFile>,
+    max_pieces:usize,           // of the largest wdl table found
+}
+
+impl Tablebase{
+    pub fn open(params:&TablebaseParams)->Result<Tablebase, String>{
+        // finds the tables of every directory in the path, they are read when first probed
+        let mut tablebase = Tablebase{params:params.clone(), wdl:HashMap::new(), dtz:HashMap::new(), max_pieces:0};
+        for directory in std::env::split_paths(&params.path){
+            let entries = std::fs::read_dir(&directory).map_err(|error| format!("cannot read {}: {}", directory.display(), error))?;
+            for entry in entries.flatten(){
+                let path = entry.path();
+                let (Some(name), Some(extension)) = (path.file_stem().and_then(|stem| stem.to_str()), path.extension().and_then(|extension| extension.to_str())) else{
+                    continue;
+                };
+                if !valid_name(name){
+                    continue;
+                }
+                let name = name.to_string();
+                match extension{
+                    "rtbw"=>{
+                        tablebase.max_pieces = tablebase.max_pieces.max(name.len()-1);
+                        tablebase.wdl.insert(name, TableFile{path, table:OnceLock::new()});
+                    }
+                    "rtbz"=>{
+                        tablebase.dtz.insert(name, TableFile{path, table:OnceLock::new()});
+                    }
+                    _=>{}
+                }
+            }
+        }
+        if tablebase.wdl.is_empty(){
+            return Err(format!("no syzygy tables in {}", params.path));
+        }
+        Ok(tablebase)
+    }
+
+    pub fn len(&self)->usize{
+        self.wdl.len() + self.dtz.len()
+    }
+
+    pub fn probes(&self, board:&Board, depth:i32)->bool{
+        // positions at the probe limit are only probed from probe_depth on, there are the most of them
+        let pieces = (board.whites | board.blacks).count_ones() as usize;
+        let limit = self.params.probe_limit.min(self.max_pieces);
+        pieces <= limit && (pieces < limit || depth >= self.params.probe_depth)
+    }
+
+    fn table(&self, board:&Board, dtz:bool)->Option<&Table>{
+        let tables = if dtz{&self.dtz}else{&self.wdl};
+        let (white, black) = (material(board, board.whites), material(board, board.blacks));
+        let (name, file) = tables.get_key_value(&format!("{}v{}", white, black))
+            .or_else(|| tables.get_key_value(&format!("{}v{}", black, white)))?;
+        file.table(name, dtz)
+    }
+
+    fn probe_table(&self, board:&Board, white:bool, dtz:bool, wdl:i32)->Option<Option<i32>>{
+        // None without a table, Some(None) when the dtz table has the other side to move
+        if (board.whites | board.blacks).count_ones() == 2{
+            return Some(Some(0));
+        }
+        Some(self.table(board, dtz)?.probe(board, white, wdl))
+    }
+
+    fn search(&self, board:&Board, white:bool, pawn_moves:bool)->Option<(i32, bool)>{
+        // the tables may store any value where a capture is best, whatever compresses
+        // well, so the captures are searched and the best result counts. the dtz tables
+        // do the same for pawn moves, those are searched too with pawn_moves. the flag
+        // tells that the result comes from one of these zeroing moves
+        let moves = legal_moves(board, white);
+        let mut best = -2;
+        let mut searched = 0;
+        for child in &moves{
+            let zeroing = is_capture(board, child) || (pawn_moves && child.fifty_rule == 0);
+            if !zeroing{
+                continue;
+            }
+            searched += 1;
+            let value = -self.search(child, !white, false)?.0;
+            if value > best{
+                best = value;
+                if value == 2{
+                    return Some((value, true));
+                }
+            }
+        }
+        // with only zeroing moves the stored value is not needed and may be wrong
+        let all_searched = searched > 0 && searched == moves.len();
+        let value = if all_searched{best}else{self.probe_table(board, white, false, 0)??};
+        if best >= value{
+            return Some((best, best > 0 || all_searched));
+        }
+        Some((value, false))
+    }
+
+    pub fn probe_wdl(&self, board:&Board, white:bool)->Option<i32>{
+        // -2 loss, -1 blessed loss, 0 draw, 1 cursed win, 2 win for the side to move.
+        // cursed and blessed results are wins and losses the fifty move rule makes draws
+        self.search(board, white, false).map(|(wdl, _)| wdl)
+    }
+
+    pub fn probe_dtz(&self, board:&Board, white:bool)->Option<i32>{
+        // plies to the next capture or pawn move with best play, positive when winning,
+        // 100 more for cursed wins and blessed losses, and 0 for draws
+        let (wdl, zeroing) = self.search(board, white, true)?;
+        if wdl == 0{
+            return Some(0);
+        }
+        if zeroing{
+            return Some(dtz_before_zeroing(wdl));
+        }
+        if let Some(dtz) = self.probe_table(board, white, true, wdl)?{
+            let cursed = if wdl.abs() == 1{100}else{0};
+            return Some((dtz + cursed)*wdl.signum());
+        }
+        // the table only has the other side to move, so the best move is found a ply deeper
+        let mut best = i32::MAX;
+        for child in legal_moves(board, white){
+            let zeroing = child.fifty_rule == 0;
+            let mut dtz = if zeroing{
+                -dtz_before_zeroing(self.search(&child, !white, false)?.0)
+            }else{
+                -self.probe_dtz(&child, !white)?
+            };
+            if dtz == 1 && is_mate(&child, !white){
+                best = 1;
+            }
+            if !zeroing{
+                dtz += dtz.signum();
+            }
+            if dtz < best && dtz.signum() == wdl.signum(){
+                best = dtz;
+            }
+        }
+        Some(if best == i32::MAX{-1}else{best})
+    }
+
+    pub fn rank_root_moves(&self, board:&Board, white:bool)->Option<Vec<(Board, i32, i32)>>{
+        // every legal move with its dtz counted from this position and a rank, higher
+        // is better. wins rank by how soon they zero the fifty move counter and losses
+        // by how late, so the winning side always makes progress. with the fifty move
+        // rule wins that come too late rank just above the draws, losses just below
+        if !self.probes(board, i32::MAX){
+            return None;
+        }
+        let fifty = board.fifty_rule as i32;
+        let mut ranked = Vec::new();
+        for child in legal_moves(board, white){
+            let mut dtz = if child.fifty_rule == 0{
+                dtz_before_zeroing(-self.probe_wdl(&child, !white)?)
+            }else{
+                let dtz = -self.probe_dtz(&child, !white)?;
+                dtz + dtz.signum()
+            };
+            if dtz == 2 && is_mate(&child, !white){
+                dtz = 1;
+            }
+            let in_time = !self.params.fifty_move_rule || dtz.abs() + fifty <= 100;
+            let rank = match (dtz.signum(), in_time){
+                (1, true)=>2*MAX_DTZ - dtz,
+                (1, false)=>MAX_DTZ - dtz,
+                (-1, false)=>-MAX_DTZ - dtz,
+                (-1, true)=>-2*MAX_DTZ - dtz,
+                _=>0,
+            };
+            ranked.push((child, dtz, rank));
+        }
+        Some(ranked)
+    }
+
+    pub fn root_moves(&self, board:&Board, white:bool)->Option<(Vec<Move>, Score)>{
+        // the root moves the tables rank below the best, to be left out of the search,
+        // and the score of the position
+        let ranked = self.rank_root_moves(board, white)?;
+        let best = ranked.iter().map(|(_, _, rank)| *rank).max()?;
+        let excluded = ranked.iter()
+            .filter(|(_, _, rank)| *rank < best)
+            .map(|(child, _, _)| Move::between(board, child, white))
+            .collect();
+        let wdl = match best{
+            rank if rank > MAX_DTZ=>2,
+            rank if rank > 0=>1,
+            0=>0,
+            rank if rank > -MAX_DTZ=>-1,
+            _=>-2,
+        };
+        Some((excluded, self.score(wdl, 0)))
+    }
+
+    pub fn score(&self, wdl:i32, ply:i16)->Score{
+        // wins and losses above every evaluation, nearer ones first. with the fifty
+        // move rule cursed wins and blessed losses are draws, just off zero
+        let draw_margin = if self.params.fifty_move_rule{1}else{0};
+        if wdl > draw_margin{
+            return Score::TB_WIN - ply;
+        }
+        if wdl < -draw_margin{
+            return -Score::TB_WIN + ply;
+        }
+        Score::DRAW + wdl as i16
+    }
+}
+
+static TABLEBASE:RwLock<Option<Arc<Tablebase>>> = RwLock::new(None);
+
+pub fn tablebase()->Option<Arc<Tablebase>>{
+    // the tables searches probe, None until a path is set
+    return TABLEBASE.read().unwrap().clone();
+}
+
+pub fn set_tablebase(params:&TablebaseParams)->Result<usize, String>{
+    // opens the tables for every search started afterwards, an empty path closes them.
+    // returns the number of table files found
+    let tablebase = if params.path.is_empty(){None}else{Some(Tablebase::open(params)?)};
+    let count = tablebase.as_ref().map_or(0, Tablebase::len);
+    *TABLEBASE.write().unwrap() = tablebase.map(Arc::new);
+    Ok(count)
+}
+
+pub fn run(args:&[String])->Result<(), String>{
+    // syzygy probe [fen]: the result of a position and of every move in it,
+    // with the tables from the [tablebase] path of --params
+    match args{
+        [command, fen @ ..] if command == "probe"=>{
+            let tablebase = tablebase().ok_or("syzygy probe needs tables, set the [tablebase] path with --params")?;
+            let fen = fen.join(" ");
+            let (board, white) = parse_fen(if fen.is_empty(){START_FEN}else{&fen})?;
+            let wdl = tablebase.probe_wdl(&board, white).ok_or("the position is not in the tables")?;
+            let dtz = tablebase.probe_dtz(&board, white).ok_or("there is no dtz table for the position")?;
+            println!("{} dtz {}", WDL_NAMES[(wdl+2) as usize], dtz);
+            let mut ranked = tablebase.rank_root_moves(&board, white).ok_or("there is no dtz table for a move")?;
+            ranked.sort_by_key(|(_, _, rank)| Reverse(*rank));
+            for (child, dtz, _) in ranked{
+                println!("{:8} dtz {}", san(&board, &child, white), dtz);
+            }
+            Ok(())
+        }
+        _=>Err("usage: syzygy probe [fen]".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn tablebase(fifty_move_rule:bool)->Tablebase{
+        let params = TablebaseParams{fifty_move_rule, ..TablebaseParams::default()};
+        Tablebase{params, wdl:HashMap::new(), dtz:HashMap::new(), max_pieces:5}
+    }
+
+    #[test]
+    fn index_tables_number_every_square_once(){
+        let indices = indices();
+        assert_eq!((indices.binomial[2][5], indices.binomial[3][6], indices.binomial[0][0]), (10, 20, 1));
+        let triangle:Vec<usize> = (0..64).filter(|&square| square < 32 && file_of(square) <= 3 && off_diagonal(square) <= 0)
+            .map(|square| indices.triangle[square]).collect();
+        assert_eq!(triangle.len(), 10);
+        assert!((0..10).all(|code| triangle.contains(&code)));
+        // the diagonal squares a1, b2, c3 and d4 come last
+        assert_eq!([0, 9, 18, 27].map(|square| indices.triangle[square]), [6, 7, 8, 9]);
+        let mut below:Vec<usize> = (0..64).filter(|&square| off_diagonal(square) < 0).map(|square| indices.below_diagonal[square]).collect();
+        below.sort();
+        assert_eq!(below, (0..28).collect::<Vec<_>>());
+        let mut pawns:Vec<usize> = (8..56).map(|square| indices.pawns[square]).collect();
+        pawns.sort();
+        assert_eq!(pawns, (0..48).collect::<Vec<_>>());
+        // a single leading pawn has six ranks on each of the files a to d
+        assert_eq!(indices.lead_pawns_size[1], [6; 4]);
+    }
+
+    #[test]
+    fn king_pairs_have_462_codes(){
+        let indices = indices();
+        let mut codes = Vec::new();
+        for first in (0..64).filter(|&square| square < 32 && file_of(square) <= 3 && off_diagonal(square) <= 0){
+            for second in 0..64{
+                let touching = file_of(first).abs_diff(file_of(second)) <= 1 && rank_of(first).abs_diff(rank_of(second)) <= 1;
+                let above_diagonal = off_diagonal(first) == 0 && off_diagonal(second) > 0;
+                if !touching && !above_diagonal{
+                    codes.push(indices.kings[indices.triangle[first]][second]);
+                }
+            }
+        }
+        codes.sort();
+        assert_eq!(codes, (0..462).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn unique_indices_cover_31332_placements(){
+        // three different pieces, the first in the triangle, none above the
+        // diagonal while every piece before it is on it
+        let mut seen = vec![false; 31332];
+        for first in (0..64).filter(|&square| square < 32 && file_of(square) <= 3 && off_diagonal(square) <= 0){
+            for second in (0..64).filter(|&square| square != first && (off_diagonal(first) != 0 || off_diagonal(square) <= 0)){
+                let on_diagonal = off_diagonal(first) == 0 && off_diagonal(second) == 0;
+                for third in (0..64).filter(|&square| square != first && square != second && (!on_diagonal || off_diagonal(square) <= 0)){
+                    let index = unique_index(&[first, second, third]) as usize;
+                    assert!(!seen[index], "{} {} {}", first, second, third);
+                    seen[index] = true;
+                }
+            }
+        }
+        assert!(seen.iter().all(|&used| used));
+    }
+
+    #[test]
+    fn positions_are_named_and_listed_like_the_tables(){
+        let (board, _) = parse_fen("8/8/8/3k4/8/1P6/8/K1R5 w - - 0 1").unwrap();
+        assert_eq!((material(&board, board.whites), material(&board, board.blacks)), ("KRP".to_string(), "K".to_string()));
+        assert_eq!(pieces(&board), [(0, 6), (2, 4), (17, 1), (35, 14)]);
+        let (start, _) = parse_fen(START_FEN).unwrap();
+        assert_eq!(material(&start, start.whites), "KQRRBBNNPPPPPPPP");
+        for name in ["KvK", "KQvK", "KRPvKR", "KQRBvKNP"]{
+            assert!(valid_name(name), "{}", name);
+        }
+        for name in ["KQ", "QvK", "KXvK", "KQRBNvKPP", "kqvk"]{
+            assert!(!valid_name(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn reads_are_little_endian_and_zero_past_the_end(){
+        let bytes = [0x01, 0x02, 0x03, 0x04, 0x05];
+        assert_eq!(read_u16(&bytes, 0), 0x0201);
+        assert_eq!(read_u32(&bytes, 1), 0x05040302);
+        assert_eq!(read_u32_be(&bytes, 0), 0x01020304);
+        assert_eq!(read_u32(&bytes, 3), 0x0504);
+        assert_eq!(read_u64_be(&bytes, 0), 0x0102030405000000);
+    }
+
+    #[test]
+    fn results_become_scores(){
+        assert_eq!([2, 1, 0, -1, -2].map(dtz_before_zeroing), [1, 101, 0, -101, -1]);
+        let fifty = tablebase(true);
+        assert_eq!(fifty.score(2, 7), Score::TB_WIN - 7);
+        assert_eq!(fifty.score(-2, 7), -Score::TB_WIN + 7);
+        // cursed wins and blessed losses are draws, just off zero
+        assert_eq!(fifty.score(1, 7), Score::DRAW + 1);
+        assert_eq!(fifty.score(-1, 7), Score::DRAW - 1);
+        assert_eq!(fifty.score(0, 7), Score::DRAW);
+        let no_fifty = tablebase(false);
+        assert_eq!(no_fifty.score(1, 3), Score::TB_WIN - 3);
+        assert_eq!(no_fifty.score(-1, 3), -Score::TB_WIN + 3);
+    }
+
+    #[test]
+    fn only_small_enough_positions_are_probed(){
+        let mut tablebase = tablebase(true);
+        tablebase.params.probe_depth = 4;
+        let (four, _) = parse_fen("8/8/8/3k4/8/1P6/8/K1R5 w - - 0 1").unwrap();
+        let (five, _) = parse_fen("8/8/8/3k4/8/1P6/8/KRR5 w - - 0 1").unwrap();
+        let (six, _) = parse_fen("8/8/8/3k4/8/1P6/8/KRRR4 w - - 0 1").unwrap();
+        assert!(tablebase.probes(&four, 1));
+        // positions at the limit wait for probe_depth
+        assert!(!tablebase.probes(&five, 3) && tablebase.probes(&five, 4));
+        assert!(!tablebase.probes(&six, 20));
+    }
+
+    #[test]
+    fn tables_are_found_by_their_names(){
+        let directory = std::env::temp_dir().join(format!("chessbot-syzygy-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let params = TablebaseParams{path:directory.to_string_lossy().to_string(), ..TablebaseParams::default()};
+        assert!(Tablebase::open(&params).is_err());
+        for name in ["KQvK.rtbw", "KQvK.rtbz", "KRPvKR.rtbw", "notes.rtbw", "KQvK.txt"]{
+            std::fs::write(directory.join(name), b"").unwrap();
+        }
+        let tablebase = Tablebase::open(&params);
+        std::fs::remove_dir_all(&directory).unwrap();
+        let tablebase = tablebase.unwrap();
+        assert_eq!((tablebase.len(), tablebase.max_pieces), (3, 5));
+        // the empty file is broken, so probes fail instead of guessing
+        let (board, white) = parse_fen("8/8/8/3k4/8/8/8/KQ6 w - - 0 1").unwrap();
+        assert_eq!(tablebase.probe_wdl(&board, white), None);
+    }
+}
@@ -0,0 +1,112 @@
+// evaluation breakdown per term and colour, to see why a position scores
+// the way it does and to diff evaluations between versions
+
+use crate::notation::{parse_fen, START_FEN};
+use crate::score::Score;
+use crate::endgame::{self, Endgame};
+use crate::{eval, pawns, Board};
+
+pub struct Term{
+    pub name:&'static str,
+    pub white:(i32, i32),   // (middlegame, endgame) in centipawns
+    pub black:(i32, i32),
+}
+
+pub struct EvalTrace{
+    pub terms:Vec<Term>,
+    pub phase:(i32, i32),   // contribution of the white and the black pieces
+    pub white:bool,         // side to move
+    pub score:Score,        // what evaluate_position returns, for the side to move
+    pub endgame:Option<Endgame>,    // replaces the terms when it applies
+}
+
+pub fn trace(board:&Board, white:bool)->EvalTrace{
+    let p = eval::params();
+    let mut terms = Vec::new();
+    let (white_material, white_tables) = eval::side_material_and_tables(board, p, true);
+    let (black_material, black_tables) = eval::side_material_and_tables(board, p, false);
+    terms.push(Term{name:"material", white:white_material, black:black_material});
+    terms.push(Term{name:"tables", white:white_tables, black:black_tables});
+    terms.push(Term{name:"pawns", white:pawns::side_pawn_structure(board, p, true), black:pawns::side_pawn_structure(board, p, false)});
+    terms.push(Term{name:"mobility", white:eval::side_mobility(board, p, true), black:eval::side_mobility(board, p, false)});
+    terms.push(Term{name:"king safety", white:(eval::side_king_safety(board, p, true), 0), black:(eval::side_king_safety(board, p, false), 0)});
+
+    let mut phase = (0, 0);
+    for (piece, piece_map) in eval::piece_maps(board).iter().enumerate(){
+        phase.0 += eval::PHASE_WEIGHTS[piece]*(piece_map & board.whites).count_ones() as i32;
+        phase.1 += eval::PHASE_WEIGHTS[piece]*(piece_map & board.blacks).count_ones() as i32;
+    }
+
+    let score = board.clone().evaluate_position(white);
+    let endgame = endgame::recognise(board).map(|(endgame, _)| endgame)
+        .filter(|_| endgame::evaluate(board, white).is_some());
+    let trace = EvalTrace{terms, phase, white, score, endgame};
+    debug_assert!(endgame.is_some() || trace.recomputed(board) == score, "trace does not add up to evaluate_position");
+    trace
+}
+
+impl EvalTrace{
+    fn totals(&self)->((i32, i32), (i32, i32)){
+        // summed (middlegame, endgame) of white and of black
+        let mut white = (0, 0);
+        let mut black = (0, 0);
+        for term in self.terms.iter(){
+            white = (white.0 + term.white.0, white.1 + term.white.1);
+            black = (black.0 + term.black.0, black.1 + term.black.1);
+        }
+        (white, black)
+    }
+
+    fn recomputed(&self, board:&Board)->Score{
+        // the score the terms add up to, blended the same way evaluate_position does
+        let (white, black) = self.totals();
+        eval::blend(board, black.0-white.0, black.1-white.1, self.white)
+    }
+
+    pub fn to_text(&self)->String{
+        let mut text = format!("{:<12}{:>16}{:>16}{:>16}\n", "term", "white", "black", "white-black");
+        text.push_str(&format!("{:<12}{:>8}{:>8}{:>8}{:>8}{:>8}{:>8}\n", "", "mg", "eg", "mg", "eg", "mg", "eg"));
+        let (white, black) = self.totals();
+        let rows = self.terms.iter().map(|term| (term.name, term.white, term.black))
+            .chain(std::iter::once(("total", white, black)));
+        for (name, white, black) in rows{
+            text.push_str(&format!("{:<12}{:>8}{:>8}{:>8}{:>8}{:>8}{:>8}\n",
+                name, white.0, white.1, black.0, black.1, white.0-black.0, white.1-black.1));
+        }
+        text.push_str(&format!("phase {} of {} (white {}, black {})\n",
+            (self.phase.0 + self.phase.1).min(eval::MAX_PHASE), eval::MAX_PHASE, self.phase.0, self.phase.1));
+        if let Some(endgame) = self.endgame{
+            text.push_str(&format!("endgame {}, the terms above are not used\n", endgame.name()));
+        }
+        text.push_str(&format!("score {} for {}\n", self.score, if self.white{"white"}else{"black"}));
+        text
+    }
+
+    pub fn to_json(&self)->String{
+        // one line, keys in a fixed order so two versions can be diffed directly
+        let pair = |(mg, eg):(i32, i32)| format!("{{\"mg\":{},\"eg\":{}}}", mg, eg);
+        let (white, black) = self.totals();
+        let terms:Vec<String> = self.terms.iter()
+            .map(|term| format!("\"{}\":{{\"white\":{},\"black\":{}}}", term.name, pair(term.white), pair(term.black)))
+            .collect();
+        format!("{{\"side\":\"{}\",\"terms\":{{{}}},\"total\":{{\"white\":{},\"black\":{}}},\"phase\":{{\"white\":{},\"black\":{},\"max\":{}}},\"endgame\":{},\"score\":{}}}",
+            if self.white{"white"}else{"black"}, terms.join(","), pair(white), pair(black),
+            self.phase.0, self.phase.1, eval::MAX_PHASE,
+            self.endgame.map_or("null".to_string(), |endgame| format!("\"{}\"", endgame.name())),
+            self.score.centipawns())
+    }
+}
+
+pub fn run(args:&[String])->Result<(), String>{
+    // trace [--json] [fen], the start position if no fen is given
+    let json = args.iter().any(|arg| arg == "--json");
+    let fen = args.iter().filter(|arg| *arg != "--json").cloned().collect::<Vec<String>>().join(" ");
+    let (board, white) = parse_fen(if fen.is_empty(){START_FEN}else{&fen})?;
+    let trace = trace(&board, white);
+    if json{
+        println!("{}", trace.to_json());
+    }else{
+        print!("{}", trace.to_text());
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+// zobrist hashing and the transposition table shared between search threads
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::Board;
+use crate::notation::Move;
+use crate::score::Score;
+
+const fn zobrist_keys()->[u64; 12*64+1]{
+    // splitmix64, so the keys are the same on every run and every thread
+    let mut keys = [0u64; 12*64+1];
+    let mut state:u64 = 0x2545_F491_4F6C_DD1D;
+    let mut i = 0;
+    while i < keys.len(){
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+}
+
+const ZOBRIST_KEYS:[u64; 12*64+1] = zobrist_keys();
+const WHITE_TO_MOVE_KEY:usize = 12*64;
+
+pub fn zobrist(board:&Board, white:bool)->u64{
+    let pieces = [board.pawns, board.knights, board.bishops, board.rooks, board.queens, board.kings];
+    let mut hash = if white{ZOBRIST_KEYS[WHITE_TO_MOVE_KEY]}else{0};
+    for (piece_index, piece_map) in pieces.iter().enumerate(){
+        for (color_index, color_map) in [board.whites, board.blacks].iter().enumerate(){
+            let mut squares = piece_map & color_map;
+            while squares != 0{
+                let square = squares.trailing_zeros() as usize;
+                hash ^= ZOBRIST_KEYS[(2*piece_index + color_index)*64 + square];
+                squares &= squares-1;
+            }
+        }
+    }
+    hash
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Bound{
+    Exact,
+    Lower,  // the score failed high, the real score is at least this
+    Upper,  // the score failed low, the real score is at most this
+}
+
+#[derive(Clone, Copy)]
+pub struct TtEntry{
+    pub score:Score,
+    pub depth:i32,
+    pub bound:Bound,
+    pub best_move:Option<Move>,
+}
+
+impl TtEntry{
+    fn pack(&self)->u64{
+        let bound = match self.bound{Bound::Exact=>1u64, Bound::Lower=>2, Bound::Upper=>3};
+        let mut data = self.score.centipawns() as u16 as u64
+            | (self.depth.clamp(0, 255) as u64)<<16
+            | bound<<24;
+        if let Some(best_move) = self.best_move{
+            data |= 1<<32 | (best_move.from as u64)<<40 | (best_move.to as u64)<<48;
+        }
+        data
+    }
+
+    fn unpack(data:u64)->Option<TtEntry>{
+        let bound = match (data>>24) & 3{
+            1=>Bound::Exact,
+            2=>Bound::Lower,
+            3=>Bound::Upper,
+            _=>return None,
+        };
+        let best_move = if data & 1<<32 != 0{
+            Some(Move{from:(data>>40) as u8, to:(data>>48) as u8})
+        }else{
+            None
+        };
+        Some(TtEntry{
+            score:Score::new(data as u16 as i16),
+            depth:((data>>16) & 255) as i32,
+            bound,
+            best_move,
+        })
+    }
+}
+
+struct Slot{
+    // the key is stored xored with the data, so a slot torn by two threads
+    // writing at once fails verification instead of returning garbage
+    key:AtomicU64,
+    data:AtomicU64,
+}
+
+pub struct TranspositionTable{
+    slots:Vec<Slot>,
+    mask:usize,
+}
+
+impl TranspositionTable{
+    pub fn new(megabytes:usize)->TranspositionTable{
+        let wanted = (megabytes.max(1) << 20) / std::mem::size_of::<Slot>();
+        let len = if wanted.is_power_of_two(){wanted}else{wanted.next_power_of_two()>>1};
+        let slots = (0..len).map(|_| Slot{key:AtomicU64::new(0), data:AtomicU64::new(0)}).collect();
+        TranspositionTable{slots, mask:len-1}
+    }
+
+    pub fn clear(&self){
+        // forget every entry, between unrelated games
+        for slot in &self.slots{
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn probe(&self, hash:u64)->Option<TtEntry>{
+        let slot = &self.slots[hash as usize & self.mask];
+        let data = slot.data.load(Ordering::Relaxed);
+        if slot.key.load(Ordering::Relaxed) ^ data != hash{
+            return None;
+        }
+        TtEntry::unpack(data)
+    }
+
+    pub fn store(&self, hash:u64, entry:TtEntry){
+        let slot = &self.slots[hash as usize & self.mask];
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let same_position = slot.key.load(Ordering::Relaxed) ^ old_data == hash;
+        if same_position{
+            // keep the deeper result for the same position
+            if let Some(old) = TtEntry::unpack(old_data){
+                if old.depth > entry.depth && entry.bound != Bound::Exact{
+                    return;
+                }
+            }
+        }
+        let data = entry.pack();
+        slot.key.store(hash ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+pub fn score_to_tt(score:Score, ply:i16)->Score{
+    // mate and tablebase scores are stored relative to the node, not the root
+    if score.is_decisive(){
+        return if score > Score::DRAW{score + ply}else{score - ply};
+    }
+    score
+}
+
+pub fn score_from_tt(score:Score, ply:i16)->Score{
+    if score.is_decisive(){
+        return if score > Score::DRAW{score - ply}else{score + ply};
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::notation::{parse_fen, parse_san, START_FEN};
+
+    fn same(a:&TtEntry, b:&TtEntry)->bool{
+        a.score == b.score && a.depth == b.depth && a.bound == b.bound && a.best_move == b.best_move
+    }
+
+    #[test]
+    fn entries_pack_and_unpack(){
+        for score in [Score::DRAW, Score::new(-1234), Score::MAX_EVAL, -Score::MAX_EVAL, Score::mated_in(3), -Score::mated_in(7), Score::TB_WIN - 5]{
+            for bound in [Bound::Exact, Bound::Lower, Bound::Upper]{
+                for best_move in [None, Some(Move{from:0, to:63}), Some(Move{from:12, to:28})]{
+                    let entry = TtEntry{score, depth:17, bound, best_move};
+                    assert!(same(&TtEntry::unpack(entry.pack()).unwrap(), &entry));
+                }
+            }
+        }
+        // depths beyond a byte are kept at the largest one
+        let deep = TtEntry{score:Score::DRAW, depth:300, bound:Bound::Exact, best_move:None};
+        assert_eq!(TtEntry::unpack(deep.pack()).unwrap().depth, 255);
+        assert!(TtEntry::unpack(0).is_none());
+    }
+
+    #[test]
+    fn probes_verify_the_key(){
+        let tt = TranspositionTable::new(1);
+        let entry = TtEntry{score:Score::new(42), depth:5, bound:Bound::Lower, best_move:Some(Move{from:11, to:27})};
+        let hash = 0x1234_5678_9ABC_DEF0;
+        tt.store(hash, entry);
+        assert!(same(&tt.probe(hash).unwrap(), &entry));
+        // the same slot with a different key
+        assert!(tt.probe(hash ^ 1<<63).is_none());
+        // a slot torn by two writers fails verification
+        let slot = &tt.slots[hash as usize & tt.mask];
+        slot.data.store(TtEntry{depth:9, ..entry}.pack(), Ordering::Relaxed);
+        assert!(tt.probe(hash).is_none());
+        tt.clear();
+        assert!(tt.probe(hash).is_none());
+    }
+
+    #[test]
+    fn transpositions_share_a_key(){
+        let play = |moves:&[&str]|{
+            let (mut board, mut white) = parse_fen(START_FEN).unwrap();
+            for text in moves{
+                board = parse_san(&board, white, text).unwrap();
+                white = !white;
+            }
+            zobrist(&board, white)
+        };
+        assert_eq!(play(&["Nf3", "Nf6", "Nc3"]), play(&["Nc3", "Nf6", "Nf3"]));
+        assert_ne!(play(&["Nf3", "Nf6", "Nc3"]), play(&["Nf3", "Nf6", "Nc3", "Ng8", "Ng1", "Nf6"]));
+        let (board, _) = parse_fen(START_FEN).unwrap();
+        assert_ne!(zobrist(&board, true), zobrist(&board, false));
+    }
+
+    #[test]
+    fn decisive_scores_are_stored_relative_to_the_node(){
+        for score in [-Score::mated_in(9), Score::mated_in(6), Score::TB_WIN - 8, -Score::TB_WIN + 8]{
+            let stored = score_to_tt(score, 4);
+            assert_eq!(score_from_tt(stored, 4), score);
+            // the same node found two plies nearer the root
+            assert_eq!(score_from_tt(stored, 2).centipawns().abs(), score.centipawns().abs() + 2);
+        }
+        assert_eq!(score_to_tt(Score::new(150), 4), Score::new(150));
+    }
+}
@@ -0,0 +1,225 @@
+// texel style tuning: fit the evaluation weights to game results by
+// minimising the squared error between the result and a sigmoid of the
+// static evaluation, one weight at a time
+
+use crate::eval::{self, EvalParams};
+use crate::notation::parse_fen;
+use crate::params::Params;
+use crate::Board;
+
+pub struct Sample{
+    pub board:Board,
+    pub result:f64,     // from whites point of view, 1 win, 0.5 draw, 0 loss
+}
+
+fn parse_result(token:&str)->Option<f64>{
+    let token = token.trim_matches(|symbol| "\"[];".contains(symbol));
+    match token{
+        "1-0" | "1.0" | "1"=>Some(1.0),
+        "0-1" | "0.0" | "0"=>Some(0.0),
+        "1/2-1/2" | "0.5"=>Some(0.5),
+        _=>None,
+    }
+}
+
+pub fn parse_sample(line:&str)->Result<Option<Sample>, String>{
+    // a fen followed by the result, as 1-0, [1.0] or an epd c9 "1-0"; opcode.
+    // blank lines and lines starting with # are skipped
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#'){
+        return Ok(None);
+    }
+    let mut tokens:Vec<&str> = line.split_whitespace().collect();
+    let Some(result) = tokens.pop().and_then(parse_result) else{
+        return Err(format!("no game result at the end of '{}'", line));
+    };
+    if tokens.last().is_some_and(|token| token.starts_with('c') && token[1..].parse::<u8>().is_ok()){
+        tokens.pop();
+    }
+    let (board, _) = parse_fen(&tokens.join(" "))?;
+    Ok(Some(Sample{board, result}))
+}
+
+pub fn load_samples(path:&str)->Result<Vec<Sample>, String>{
+    let text = std::fs::read_to_string(path).map_err(|error| format!("cannot read {}: {}", path, error))?;
+    let mut samples = Vec::new();
+    for (number, line) in text.lines().enumerate(){
+        if let Some(sample) = parse_sample(line).map_err(|error| format!("{} line {}: {}", path, number+1, error))?{
+            samples.push(sample);
+        }
+    }
+    Ok(samples)
+}
+
+fn sigmoid(score:f64, k:f64)->f64{
+    // expected result for a score in centipawns, k scales pawns to winning chances
+    1.0/(1.0 + 10f64.powf(-k*score/400.0))
+}
+
+pub fn mean_error(samples:&[Sample], params:&EvalParams, k:f64, threads:usize)->f64{
+    let chunk_size = samples.len().div_ceil(threads.max(1)).max(1);
+    let total:f64 = std::thread::scope(|scope|{
+        let handles:Vec<_> = samples.chunks(chunk_size).map(|chunk| scope.spawn(move ||{
+            chunk.iter().map(|sample|{
+                let score = eval::evaluate(&sample.board, params, true).centipawns() as f64;
+                (sample.result - sigmoid(score, k)).powi(2)
+            }).sum::<f64>()
+        })).collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).sum()
+    });
+    total / samples.len().max(1) as f64
+}
+
+pub fn fit_k(samples:&[Sample], params:&EvalParams, threads:usize)->f64{
+    // the scaling constant is fitted once for the starting weights and then
+    // kept fixed, so the weights stay in centipawns
+    let mut best = (1.0, mean_error(samples, params, 1.0, threads));
+    let mut step = 0.5;
+    while step >= 0.001{
+        for k in [best.0 - step, best.0 + step]{
+            if k <= 0.0{
+                continue;
+            }
+            let error = mean_error(samples, params, k, threads);
+            if error < best.1{
+                best = (k, error);
+            }
+        }
+        step /= 2.0;
+    }
+    best.0
+}
+
+pub fn tune(samples:&[Sample], start:&EvalParams, k:f64, step:i32, max_passes:usize, threads:usize)->EvalParams{
+    // every weight is nudged up and down by step and the change is kept when
+    // it lowers the error, until a whole pass improves nothing
+    let mut params = start.clone();
+    let mut best_error = mean_error(samples, &params, k, threads);
+    let entry_sizes:Vec<usize> = params.entries_mut().iter().map(|(_, values)| values.len()).collect();
+    println!("start error {:.6}", best_error);
+
+    for pass in 1..=max_passes{
+        let mut improved = 0;
+        for (entry, size) in entry_sizes.iter().enumerate(){
+            for index in 0..*size{
+                let original = params.entries_mut()[entry].1[index];
+                let mut best_value = original;
+                for candidate in [original+step, original-step]{
+                    params.entries_mut()[entry].1[index] = candidate;
+                    let error = mean_error(samples, &params, k, threads);
+                    if error < best_error{
+                        best_error = error;
+                        best_value = candidate;
+                        break;
+                    }
+                }
+                params.entries_mut()[entry].1[index] = best_value;
+                if best_value != original{
+                    improved += 1;
+                }
+            }
+        }
+        println!("pass {} error {:.6} changed {}", pass, best_error, improved);
+        if improved == 0{
+            break;
+        }
+    }
+    params
+}
+
+fn positive(option:&str, value:Option<&String>)->Result<usize, String>{
+    value.and_then(|value| value.parse::<usize>().ok()).filter(|&value| value > 0)
+        .ok_or(format!("{} needs a positive number", option))
+}
+
+pub fn run(args:&[String], parameters:&Params)->Result<(), String>{
+    // tune <positions file> [--out file] [--step n] [--passes n] [--threads n]
+    // the output keeps the other parameters it started with
+    let mut data = None;
+    let mut out = "tuned.toml".to_string();
+    let mut step = 1;
+    let mut passes = 100;
+    let mut threads = std::thread::available_parallelism().map_or(1, |count| count.get());
+    let mut args = args.iter();
+    while let Some(arg) = args.next(){
+        match arg.as_str(){
+            "--out"=>out = args.next().ok_or("--out needs a file name")?.clone(),
+            "--step"=>step = positive(arg, args.next())? as i32,
+            "--passes"=>passes = positive(arg, args.next())?,
+            "--threads"=>threads = positive(arg, args.next())?,
+            _ if data.is_none()=>data = Some(arg.clone()),
+            _=>return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    let data = data.ok_or("usage: tune <positions file> [--out file] [--step n] [--passes n] [--threads n]")?;
+
+    let samples = load_samples(&data)?;
+    if samples.is_empty(){
+        return Err(format!("{} has no positions", data));
+    }
+    println!("{} positions", samples.len());
+    let start = eval::params();
+    let k = fit_k(&samples, start, threads);
+    println!("k {:.3}", k);
+    let tuned = Params{eval:tune(&samples, start, k, step, passes, threads), ..parameters.clone()};
+    tuned.save(&out)?;
+    println!("wrote {}", out);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const SAMPLES:&str = "
+# one result format per line
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 1/2-1/2
+
+4k3/8/8/8/8/8/8/QQ2K3 w - - 0 1 [1.0]
+4k3/8/8/8/8/8/8/qq2K3 b - - 0 1 c9 \"0-1\";
+r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1 1
+";
+
+    fn samples()->Vec<Sample>{
+        SAMPLES.lines().filter_map(|line| parse_sample(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn samples_are_read_in_every_result_format(){
+        let results:Vec<f64> = samples().iter().map(|sample| sample.result).collect();
+        assert_eq!(results, [0.5, 1.0, 0.0, 1.0]);
+        assert!(parse_sample("4k3/8/8/8/8/8/8/4K3 w - -").is_err());
+        assert!(parse_sample("4k3/8/8/8/8/8/8/4K3 w - - 0 1 2-0").is_err());
+        assert!(parse_sample("not a fen 1-0").is_err());
+    }
+
+    #[test]
+    fn the_sigmoid_is_even_around_a_draw(){
+        assert_eq!(sigmoid(0.0, 1.3), 0.5);
+        for score in [35.0, 120.0, 900.0]{
+            assert!((sigmoid(score, 1.3) + sigmoid(-score, 1.3) - 1.0).abs() < 1e-12);
+            assert!(sigmoid(score, 1.3) > sigmoid(score, 0.7));
+        }
+        assert!(sigmoid(2000.0, 1.0) > 0.99);
+    }
+
+    #[test]
+    fn the_error_does_not_depend_on_the_threads(){
+        let samples = samples();
+        let one = mean_error(&samples, eval::params(), 1.0, 1);
+        for threads in [2, 3, 8]{
+            assert!((mean_error(&samples, eval::params(), 1.0, threads) - one).abs() < 1e-12);
+        }
+        assert_eq!(mean_error(&[], eval::params(), 1.0, 4), 0.0);
+    }
+
+    #[test]
+    fn tuning_never_raises_the_error(){
+        let samples = samples();
+        let k = fit_k(&samples, eval::params(), 2);
+        assert!(k > 0.0);
+        let start = mean_error(&samples, eval::params(), k, 2);
+        let tuned = tune(&samples, eval::params(), k, 5, 1, 2);
+        assert!(mean_error(&samples, &tuned, k, 2) <= start);
+    }
+}
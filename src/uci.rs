@@ -0,0 +1,276 @@
+// universal chess interface: commands are read from stdin and answered on
+// stdout. the search runs on a thread of its own, so stop and isready are
+// answered while it thinks
+
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::notation::{legal_moves, parse_fen, parse_uci_move, uci_move, START_FEN};
+use crate::params::{self, Params};
+use crate::random::Random;
+use crate::book::OpeningBook;
+use crate::search::{allocate_time, stop_after, SearchLimits, SearchResult, MAX_PLY};
+use crate::skill;
+use crate::syzygy;
+use crate::tt::TranspositionTable;
+use crate::{find_best_move, Board};
+
+struct Search{
+    stop:Arc<AtomicBool>,
+    handle:JoinHandle<()>,
+    // while pondering, the channel releases the bestmove and the clock
+    // starts at ponderhit with the time the go command gave
+    ponder:Option<(Sender<()>, Option<Duration>)>,
+}
+
+struct Uci{
+    params:Params,
+    tt:Arc<TranspositionTable>,
+    book:OpeningBook,
+    board:Board,
+    white:bool,
+    parent:Option<Board>,   // the position before the last move, for en passant in the book
+    ply:usize,              // moves played in the game
+    random:Arc<Mutex<Random>>,  // for a weakened engine, new with every game
+    search:Option<Search>,
+}
+
+fn number(value:Option<&&str>, name:&str)->Result<u64, String>{
+    value.and_then(|value| value.parse::<i64>().ok())
+        .map(|value| value.max(0) as u64)
+        .ok_or(format!("go {} needs a number", name))
+}
+
+impl Uci{
+    fn wait(&mut self){
+        // stops a running search, its bestmove is printed before this returns
+        if let Some(search) = self.search.take(){
+            search.stop.store(true, Ordering::Relaxed);
+            drop(search.ponder);
+            search.handle.join().unwrap();
+        }
+    }
+
+    fn ponder_hit(&mut self){
+        // the expected move was played, the ponder search goes on as a normal one
+        if let Some((release, movetime)) = self.search.as_mut().and_then(|search| search.ponder.take()){
+            let _ = release.send(());
+            if let Some(movetime) = movetime{
+                stop_after(Arc::clone(&self.search.as_ref().unwrap().stop), movetime);
+            }
+        }
+    }
+
+    fn position(&mut self, args:&[&str])->Result<(), String>{
+        // position startpos|fen <fen> [moves <move>...]
+        let split = args.iter().position(|arg| *arg == "moves").unwrap_or(args.len());
+        let (setup, moves) = args.split_at(split);
+        let (mut board, mut white) = match setup{
+            ["startpos"]=>parse_fen(START_FEN)?,
+            ["fen", fen @ ..]=>parse_fen(&fen.join(" "))?,
+            _=>return Err("usage: position startpos|fen <fen> [moves ...]".to_string()),
+        };
+        // a fen counts its plies from the full move number
+        let mut ply = match setup{
+            ["fen", .., full_moves] if setup.len() == 7=>full_moves.parse::<usize>().map_or(0, |moves| 2*moves.saturating_sub(1) + !white as usize),
+            _=>0,
+        };
+        let mut parent = None;
+        for text in moves.iter().skip(1){
+            let new_board = parse_uci_move(&board, white, text)?;
+            parent = Some(board);
+            board = new_board;
+            white = !white;
+            ply += 1;
+        }
+        self.board = board;
+        self.white = white;
+        self.parent = parent;
+        self.ply = ply;
+        Ok(())
+    }
+
+    fn set_option(&mut self, args:&[&str])->Result<(), String>{
+        // setoption name <name> value <value>
+        let split = args.iter().position(|arg| *arg == "value").unwrap_or(args.len());
+        let name = args[..split].iter().skip(1).copied().collect::<Vec<&str>>().join(" ");
+        let value = args.get(split+1..).unwrap_or(&[]).join(" ");
+        let (hash_mb, seed, book, tablebase) = (self.params.search.hash_mb, self.params.search.seed, self.params.book.clone(), self.params.tablebase.clone());
+        params::set_uci_option(&mut self.params, &name, &value)?;
+        if self.params.search.hash_mb != hash_mb{
+            self.tt = Arc::new(TranspositionTable::new(self.params.search.hash_mb));
+        }
+        if self.params.search.seed != seed{
+            self.random = Arc::new(Mutex::new(Random::new(self.params.search.seed)));
+        }
+        if self.params.book != book{
+            match OpeningBook::new(&self.params.book){
+                Ok(opened)=>self.book = opened,
+                Err(error)=>{
+                    self.params.book = book;
+                    return Err(error);
+                }
+            }
+        }
+        if self.params.tablebase != tablebase{
+            match syzygy::set_tablebase(&self.params.tablebase){
+                Ok(count)=>println!("info string found {} tablebase files", count),
+                Err(error)=>{
+                    self.params.tablebase = tablebase;
+                    return Err(error);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn go(&mut self, args:&[&str])->Result<(), String>{
+        // go [ponder] [depth n] [movetime ms] [wtime ms] [btime ms] [winc ms] [binc ms] [movestogo n] [infinite]
+        let mut depth = None;
+        let mut ponder = false;
+        let mut movetime = None;
+        let (mut time, mut increment, mut moves_to_go) = (None, 0, None);
+        let mut infinite = false;
+        let mut tokens = args.iter();
+        while let Some(token) = tokens.next(){
+            match *token{
+                "depth"=>depth = Some(number(tokens.next(), token)?.clamp(1, MAX_PLY as u64) as i32),
+                "movetime"=>movetime = Some(Duration::from_millis(number(tokens.next(), token)?)),
+                "wtime" | "btime"=>{
+                    let value = number(tokens.next(), token)?;
+                    if (*token == "wtime") == self.white{
+                        time = Some(value);
+                    }
+                }
+                "winc" | "binc"=>{
+                    let value = number(tokens.next(), token)?;
+                    if (*token == "winc") == self.white{
+                        increment = value;
+                    }
+                }
+                "movestogo"=>moves_to_go = Some(number(tokens.next(), token)?),
+                "infinite"=>infinite = true,
+                "ponder"=>ponder = true,
+                _=>{}
+            }
+        }
+        if movetime.is_none() && !infinite{
+            movetime = time.map(|time| allocate_time(time, increment, moves_to_go));
+        }
+        // without a depth the clock decides, or the parameter file when there is no clock either
+        let unlimited = infinite || movetime.is_some();
+        let limits = SearchLimits{
+            depth:depth.unwrap_or(if unlimited{MAX_PLY as i32}else{self.params.search.depth}),
+            movetime:if ponder{None}else{movetime},
+        };
+
+        let (board, white) = (self.board, self.white);
+        if legal_moves(&board, white).is_empty(){
+            println!("bestmove 0000");
+            return Ok(());
+        }
+        if let Some(book_move) = self.book.choose(&board, white, self.ply, self.parent.as_ref()).filter(|_| !ponder){
+            println!("info string book move");
+            println!("bestmove {}", uci_move(&board, &book_move, white));
+            return Ok(());
+        }
+        let params = self.params.search;
+        let tt = Arc::clone(&self.tt);
+        let random = Arc::clone(&self.random);
+        let stop = Arc::new(AtomicBool::new(false));
+        let search_stop = Arc::clone(&stop);
+        let (release, released) = mpsc::channel::<()>();
+        let handle = std::thread::spawn(move ||{
+            let mut report = |result:&SearchResult| println!("{}", result.uci_info(&board, white));
+            let mut result = find_best_move(board, white, &limits, &params, &tt, &search_stop, Some(&mut report));
+            skill::choose(&params, &mut result, &mut random.lock().unwrap());
+            if ponder{
+                // the bestmove of a ponder search waits for ponderhit or stop,
+                // a dropped sender counts as either
+                let _ = released.recv();
+            }
+            let mut best = format!("bestmove {}", uci_move(&board, &result.board, white));
+            if result.pv.len() > 1{
+                best.push_str(&format!(" ponder {}", uci_move(&result.board, &result.pv[1], !white)));
+            }
+            println!("{}", best);
+        });
+        let ponder = if ponder{Some((release, movetime))}else{None};
+        self.search = Some(Search{stop, handle, ponder});
+        Ok(())
+    }
+}
+
+pub fn run(parameters:&Params)->Result<(), String>{
+    let (board, white) = parse_fen(START_FEN)?;
+    let mut uci = Uci{
+        params:parameters.clone(),
+        tt:Arc::new(TranspositionTable::new(parameters.search.hash_mb)),
+        book:OpeningBook::new(&parameters.book)?,
+        board,
+        white,
+        parent:None,
+        ply:0,
+        random:Arc::new(Mutex::new(Random::new(parameters.search.seed))),
+        search:None,
+    };
+    for line in std::io::stdin().lock().lines(){
+        let line = line.map_err(|error| format!("cannot read stdin: {}", error))?;
+        let tokens:Vec<&str> = line.split_whitespace().collect();
+        let Some((command, args)) = tokens.split_first() else{
+            continue;
+        };
+        let result = match *command{
+            "uci"=>{
+                println!("id name chessbot {}", env!("CARGO_PKG_VERSION"));
+                println!("id author the chessbot authors");
+                for option in params::uci_options(&uci.params){
+                    println!("{}", option);
+                }
+                println!("uciok");
+                Ok(())
+            }
+            "isready"=>{
+                println!("readyok");
+                Ok(())
+            }
+            "setoption"=>{
+                uci.wait();
+                uci.set_option(args)
+            }
+            "ucinewgame"=>{
+                uci.wait();
+                uci.tt.clear();
+                uci.random = Arc::new(Mutex::new(Random::new(uci.params.search.seed)));
+                uci.position(&["startpos"])
+            }
+            "position"=>{
+                uci.wait();
+                uci.position(args)
+            }
+            "go"=>{
+                uci.wait();
+                uci.go(args)
+            }
+            "stop"=>{
+                uci.wait();
+                Ok(())
+            }
+            "ponderhit"=>{
+                uci.ponder_hit();
+                Ok(())
+            }
+            "quit"=>break,
+            _=>Err(format!("unknown command '{}'", command)),
+        };
+        if let Err(error) = result{
+            println!("info string {}", error);
+        }
+    }
+    uci.wait();
+    Ok(())
+}
@@ -0,0 +1,435 @@
+// chess engine communication protocol (xboard / winboard), version 2.
+// like the uci front-end the search runs on its own thread. the thread prints
+// the move itself and sends the new position back, and when pondering it goes
+// on to search the expected reply until the opponents move is known
+
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::notation::{legal_moves, parse_fen, parse_uci_move, pv_to_san, uci_move, Move, START_FEN};
+use crate::book::OpeningBook;
+use crate::params::Params;
+use crate::random::Random;
+use crate::search::{allocate_time, stop_after, SearchLimits, SearchParams, SearchResult, MAX_PLY};
+use crate::skill;
+use crate::syzygy::{self, TablebaseParams};
+use crate::tt::TranspositionTable;
+use crate::{find_best_move, Board};
+
+const FEATURES:&str = "feature ping=1 setboard=1 usermove=1 playother=1 sigint=0 sigterm=0 colors=0 analyze=0 egt=\"syzygy\"";
+
+struct Control{
+    discard:bool,               // the game moved on, nothing more is played or searched
+    stop:Arc<AtomicBool>,       // the stop flag of the search running now
+}
+
+enum Event{
+    Moved(Board),       // the engine sent this move
+    Pondering(Board),   // the engine thinks on the opponents time, expecting this position
+}
+
+struct Search{
+    control:Arc<Mutex<Control>>,
+    events:Receiver<Event>,
+    release:Sender<()>,         // a ponder hit, dropping it ends the pondering
+    handle:JoinHandle<()>,
+}
+
+fn begin(control:&Mutex<Control>)->Option<Arc<AtomicBool>>{
+    // a fresh stop flag for the next search, unless the game moved on
+    let mut control = control.lock().unwrap();
+    if control.discard{
+        return None;
+    }
+    control.stop = Arc::new(AtomicBool::new(false));
+    Some(Arc::clone(&control.stop))
+}
+
+struct Clock{
+    moves_per_period:u64,   // 0 for the whole game in one period
+    increment:u64,          // milliseconds
+    time_left:Option<u64>,  // milliseconds, from the last time command
+    per_move:Option<Duration>,
+}
+
+struct XBoard{
+    params:SearchParams,
+    tablebase:TablebaseParams,
+    tt:Arc<TranspositionTable>,
+    book:OpeningBook,
+    board:Board,
+    white:bool,
+    history:Vec<Board>,         // positions before each move played, for undo
+    engine_white:Option<bool>,  // the side the engine plays, None in force mode
+    post:bool,
+    ponder:bool,
+    predicted:Option<Board>,    // the position being pondered on
+    depth:Option<i32>,
+    clock:Clock,
+    random:Arc<Mutex<Random>>,  // for a weakened engine, new with every game
+    search:Option<Search>,
+}
+
+fn game_result(board:&Board, white:bool)->Option<&'static str>{
+    // the result line when the side to move has no legal move
+    if !legal_moves(board, white).is_empty(){
+        return None;
+    }
+    if !board.in_check(white){
+        return Some("1/2-1/2 {Stalemate}");
+    }
+    Some(if white{"0-1 {Black mates}"}else{"1-0 {White mates}"})
+}
+
+fn milliseconds(text:&str)->Result<u64, String>{
+    // level takes minutes or minutes:seconds
+    let (minutes, seconds) = text.split_once(':').unwrap_or((text, "0"));
+    match (minutes.parse::<u64>(), seconds.parse::<u64>()){
+        (Ok(minutes), Ok(seconds))=>Ok((minutes*60 + seconds)*1000),
+        _=>Err(format!("bad time '{}'", text)),
+    }
+}
+
+fn level(args:&[&str])->Result<Clock, String>{
+    // level moves base increment, the base time counts until the first time command
+    let [moves, base, increment] = args else{
+        return Err("bad level".to_string());
+    };
+    let (Ok(moves), Ok(increment)) = (moves.parse::<u64>(), increment.parse::<f64>()) else{
+        return Err("bad level".to_string());
+    };
+    if !increment.is_finite() || increment < 0.0{
+        return Err("bad level".to_string());
+    }
+    Ok(Clock{moves_per_period:moves, increment:(increment*1000.0) as u64, time_left:Some(milliseconds(base)?), per_move:None})
+}
+
+impl Clock{
+    fn moves_to_go(&self, plies:usize)->Option<u64>{
+        // moves left in the period, counted from the plies played so far
+        match self.moves_per_period{
+            0=>None,
+            period=>Some(period - (plies as u64/2) % period),
+        }
+    }
+}
+
+impl XBoard{
+    fn new_game(&mut self)->Result<(), String>{
+        (self.board, self.white) = parse_fen(START_FEN)?;
+        self.history.clear();
+        self.engine_white = Some(false);
+        self.depth = None;
+        self.tt.clear();
+        self.random = Arc::new(Mutex::new(Random::new(self.params.seed)));
+        Ok(())
+    }
+
+    fn play(&mut self, new_board:Board){
+        self.history.push(self.board);
+        self.board = new_board;
+        self.white = !self.white;
+    }
+
+    fn apply(&mut self, events:Vec<Event>){
+        for event in events{
+            match event{
+                Event::Moved(new_board)=>{
+                    self.play(new_board);
+                    self.predicted = None;
+                }
+                Event::Pondering(predicted)=>self.predicted = Some(predicted),
+            }
+        }
+    }
+
+    fn collect(&mut self){
+        // catches up with what the search thread did since the last command
+        if let Some(search) = &self.search{
+            let events = search.events.try_iter().collect();
+            self.apply(events);
+        }
+    }
+
+    fn abort(&mut self){
+        // ends the search thread, a move it already sent is still played
+        if let Some(Search{control, events, release, handle}) = self.search.take(){
+            {
+                let mut control = control.lock().unwrap();
+                control.discard = true;
+                control.stop.store(true, Ordering::Relaxed);
+            }
+            drop(release);
+            handle.join().unwrap();
+            self.apply(events.try_iter().collect());
+        }
+        self.predicted = None;
+    }
+
+    fn move_now(&mut self){
+        // ? plays the best move found so far, pondering is not interrupted
+        if let (Some(search), None) = (&self.search, self.predicted){
+            search.control.lock().unwrap().stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn limits(&self)->SearchLimits{
+        let moves_to_go = self.clock.moves_to_go(self.history.len());
+        let movetime = self.clock.per_move.or(self.clock.time_left.map(|time| allocate_time(time, self.clock.increment, moves_to_go)));
+        let default_depth = if movetime.is_some(){MAX_PLY as i32}else{self.params.depth};
+        SearchLimits{depth:self.depth.unwrap_or(default_depth), movetime}
+    }
+
+    fn think(&mut self){
+        // starts a search when the engine is to move
+        if self.engine_white != Some(self.white) || self.search.is_some(){
+            return;
+        }
+        if let Some(result) = game_result(&self.board, self.white){
+            println!("{}", result);
+            return;
+        }
+        if let Some(book_move) = self.book.choose(&self.board, self.white, self.history.len(), self.history.last()){
+            // the game takes the move before the gui sees it, so its reply finds the new position
+            let text = uci_move(&self.board, &book_move, self.white);
+            self.play(book_move);
+            println!("move {}", text);
+            if let Some(result) = game_result(&self.board, self.white){
+                println!("{}", result);
+            }
+            return;
+        }
+        let (board, white, post, ponder) = (self.board, self.white, self.post, self.ponder);
+        let (limits, params) = (self.limits(), self.params);
+        let tt = Arc::clone(&self.tt);
+        let random = Arc::clone(&self.random);
+        let control = Arc::new(Mutex::new(Control{discard:false, stop:Arc::new(AtomicBool::new(false))}));
+        let (send_event, events) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let thread_control = Arc::clone(&control);
+        let handle = std::thread::spawn(move ||{
+            // searches the position, plays the move, and while pondering starts
+            // over from the expected reply once the opponent has played it
+            let mut position = board;
+            let mut limits = limits;
+            let mut pondering = false;
+            while let Some(stop) = begin(&thread_control){
+                // thinking output is ply score time(centiseconds) nodes pv
+                let mut report = |result:&SearchResult|{
+                    // with multi-pv every line is posted, best first
+                    for line in result.lines.iter().filter(|_| post){
+                        println!("{} {} {} {} {}", result.depth, line.score.xboard(),
+                            result.stats.time.as_millis()/10, result.stats.nodes, pv_to_san(&position, &line.pv, white));
+                    }
+                };
+                let mut result = find_best_move(position, white, &limits, &params, &tt, &stop, Some(&mut report));
+                skill::choose(&params, &mut result, &mut random.lock().unwrap());
+                if pondering && released.recv().is_err(){
+                    return;
+                }
+                if thread_control.lock().unwrap().discard{
+                    return;
+                }
+                let text = uci_move(&position, &result.board, white);
+                let _ = send_event.send(Event::Moved(result.board));
+                println!("move {}", text);
+                if let Some(line) = game_result(&result.board, !white){
+                    println!("{}", line);
+                    return;
+                }
+                if !ponder || result.pv.len() < 2{
+                    return;
+                }
+                // the clock for the pondered move starts at the ponder hit
+                position = result.pv[1];
+                limits.movetime = None;
+                pondering = true;
+                let _ = send_event.send(Event::Pondering(position));
+            }
+        });
+        self.search = Some(Search{control, events, release, handle});
+    }
+
+    fn user_move(&mut self, text:&str){
+        let Ok(new_board) = parse_uci_move(&self.board, self.white, text) else{
+            println!("Illegal move: {}", text);
+            return;
+        };
+        if let (Some(search), Some(predicted)) = (&self.search, self.predicted){
+            if Move::between(&self.board, &predicted, self.white) == Move::between(&self.board, &new_board, self.white){
+                // ponder hit, the search already running is the one we need
+                // the move counts towards the time control before the limits are worked out
+                let stop = Arc::clone(&search.control.lock().unwrap().stop);
+                let _ = search.release.send(());
+                self.predicted = None;
+                self.play(new_board);
+                if let Some(movetime) = self.limits().movetime{
+                    stop_after(stop, movetime);
+                }
+                return;
+            }
+        }
+        self.abort();
+        self.play(new_board);
+        self.think();
+    }
+}
+
+pub fn run(parameters:&Params)->Result<(), String>{
+    let (board, white) = parse_fen(START_FEN)?;
+    let mut xboard = XBoard{
+        params:parameters.search,
+        tablebase:parameters.tablebase.clone(),
+        tt:Arc::new(TranspositionTable::new(parameters.search.hash_mb)),
+        book:OpeningBook::new(&parameters.book)?,
+        board,
+        white,
+        history:Vec::new(),
+        engine_white:Some(false),
+        post:false,
+        ponder:parameters.search.ponder,
+        predicted:None,
+        depth:None,
+        clock:Clock{moves_per_period:0, increment:0, time_left:None, per_move:None},
+        random:Arc::new(Mutex::new(Random::new(parameters.search.seed))),
+        search:None,
+    };
+    for line in std::io::stdin().lock().lines(){
+        let line = line.map_err(|error| format!("cannot read stdin: {}", error))?;
+        let tokens:Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = tokens.split_first() else{
+            continue;
+        };
+        let argument = args.first().copied().unwrap_or("");
+        // commands that leave the game alone are answered during a search,
+        // the rest end it first. a move the engine already sent is played either way
+        xboard.collect();
+        match command{
+            "xboard" | "accepted" | "rejected" | "computer" | "random" | "name" | "rating" | "otim"=>{}
+            "protover"=>{
+                println!("{} myname=\"chessbot {}\"", FEATURES, env!("CARGO_PKG_VERSION"));
+                println!("feature done=1");
+            }
+            "ping"=>println!("pong {}", argument),
+            "post"=>xboard.post = true,
+            "nopost"=>xboard.post = false,
+            "time"=>match argument.parse::<u64>(){
+                Ok(centiseconds)=>xboard.clock.time_left = Some(centiseconds*10),
+                Err(_)=>println!("Error (bad time): {}", line),
+            },
+            "?"=>xboard.move_now(),
+            // searches already running keep the tables they started with
+            "egtpath"=>match args{
+                ["syzygy", path @ ..]=>{
+                    let tablebase = TablebaseParams{path:path.join(" "), ..xboard.tablebase.clone()};
+                    match syzygy::set_tablebase(&tablebase){
+                        Ok(_)=>xboard.tablebase = tablebase,
+                        Err(error)=>println!("Error ({}): {}", error, line),
+                    }
+                }
+                _=>println!("Error (only syzygy tablebases): {}", line),
+            },
+            "hard"=>xboard.ponder = true,
+            "easy"=>{
+                xboard.ponder = false;
+                if xboard.predicted.is_some(){
+                    xboard.abort();
+                }
+            }
+            "usermove"=>xboard.user_move(argument),
+            // moves are also accepted without usermove, for guis that ignore the feature
+            _ if parse_uci_move(&xboard.board, xboard.white, command).is_ok()=>xboard.user_move(command),
+            "quit"=>break,
+            _=>{
+                xboard.abort();
+                let result = match command{
+                    "new"=>xboard.new_game(),
+                    "force"=>{
+                        xboard.engine_white = None;
+                        Ok(())
+                    }
+                    "go"=>{
+                        xboard.engine_white = Some(xboard.white);
+                        xboard.think();
+                        Ok(())
+                    }
+                    "playother"=>{
+                        xboard.engine_white = Some(!xboard.white);
+                        Ok(())
+                    }
+                    "undo" | "remove"=>{
+                        // remove takes back a move of each side
+                        for _ in 0..if command == "remove"{2}else{1}{
+                            if let Some(previous) = xboard.history.pop(){
+                                xboard.board = previous;
+                                xboard.white = !xboard.white;
+                            }
+                        }
+                        Ok(())
+                    }
+                    "setboard"=>parse_fen(&args.join(" ")).map(|(board, white)|{
+                        xboard.board = board;
+                        xboard.white = white;
+                        xboard.history.clear();
+                    }),
+                    "level"=>level(args).map(|clock| xboard.clock = clock),
+                    "st"=>argument.parse::<f64>().ok()
+                        .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+                        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                        .ok_or("bad st".to_string())
+                        .map(|per_move| xboard.clock.per_move = Some(per_move)),
+                    "sd"=>argument.parse::<i32>().map_err(|_| format!("bad depth '{}'", argument))
+                        .map(|depth| xboard.depth = Some(depth.clamp(1, MAX_PLY as i32))),
+                    "white" | "black" | "draw" | "result"=>Ok(()),
+                    _=>Err("unknown command".to_string()),
+                };
+                if let Err(error) = result{
+                    println!("Error ({}): {}", error, line);
+                }
+            }
+        }
+    }
+    xboard.abort();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn level_times_are_minutes_or_minutes_and_seconds(){
+        assert_eq!(milliseconds("5"), Ok(300000));
+        assert_eq!(milliseconds("0:30"), Ok(30000));
+        assert_eq!(milliseconds("2:05"), Ok(125000));
+        for bad in ["", "five", "1:", ":30", "1:2:3", "-1"]{
+            assert!(milliseconds(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn levels_set_the_whole_clock(){
+        let clock = level(&["40", "5", "0"]).unwrap();
+        assert_eq!((clock.moves_per_period, clock.increment, clock.time_left, clock.per_move), (40, 0, Some(300000), None));
+        let clock = level(&["0", "2:30", "1.5"]).unwrap();
+        assert_eq!((clock.moves_per_period, clock.increment, clock.time_left), (0, 1500, Some(150000)));
+        for bad in [&["40", "5"][..], &["x", "5", "0"], &["40", "5", "fast"], &["40", "5", "-2"], &["40", "5:x", "0"]]{
+            assert!(level(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn moves_to_go_restart_with_every_period(){
+        let clock = level(&["40", "5", "0"]).unwrap();
+        assert_eq!(clock.moves_to_go(0), Some(40));
+        assert_eq!(clock.moves_to_go(1), Some(40));
+        assert_eq!(clock.moves_to_go(2), Some(39));
+        assert_eq!(clock.moves_to_go(79), Some(1));
+        assert_eq!(clock.moves_to_go(80), Some(40));
+        assert_eq!(level(&["0", "5", "3"]).unwrap().moves_to_go(30), None);
+    }
+}